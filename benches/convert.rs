@@ -0,0 +1,66 @@
+//! Counts the allocations and measures the time of converting a document with many chunks.
+//!
+//! Run with `cargo bench --bench convert`.
+
+use std::{
+	alloc::{GlobalAlloc, Layout, System},
+	path::PathBuf,
+	sync::atomic::{AtomicUsize, Ordering},
+	time::Instant,
+};
+
+use typst::World;
+use typst_languagetool::convert::{self, ConvertOptions};
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting the allocations and reallocations.
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+		System.realloc(ptr, layout, new_size)
+	}
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const RUNS: u32 = 10;
+
+fn main() {
+	let text = (0..1000)
+		.map(|i| format!("Paragraph {i} has a few words of text to check."))
+		.collect::<Vec<_>>()
+		.join("\n\n");
+	let main = PathBuf::from("/typst-languagetool/main.typ");
+	let mut world = lt_world::LtWorld::new(main.clone(), None);
+	world.use_shadow_file(&main, text);
+	let doc = world.compile().unwrap();
+	let source = world.main();
+	let options = ConvertOptions::default();
+
+	let mut chunks = 0;
+	let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+	let start = Instant::now();
+	for _ in 0..RUNS {
+		// a chunk for every paragraph
+		chunks = convert::document(&doc, 1, &source, &options).unwrap().len();
+	}
+	let elapsed = start.elapsed() / RUNS;
+	let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / RUNS as usize;
+	println!(
+		"{chunks} chunks: {allocations} allocations ({:.1} per chunk) in {elapsed:?}",
+		allocations as f64 / chunks as f64
+	);
+}
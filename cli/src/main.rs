@@ -1,39 +1,66 @@
 mod output;
+mod watch;
 
 use clap::{Parser, ValueEnum};
 
 use colored::Colorize;
 use lt_world::LtWorld;
-use notify::RecursiveMode;
-use notify_debouncer_mini::new_debouncer;
-use typst_languagetool::{LanguageTool, LanguageToolBackend, Suggestion};
+use serde_json::Value;
+use typst::{layout::Em, model::Document, syntax::Source, World};
+use typst_languagetool::{
+	convert::{self, ConvertOptions},
+	CheckCache, Diagnostic, DiskCache, LanguageTool, LanguageToolBackend, Suggestion,
+};
 
 use std::{
-	collections::HashMap,
+	io::Read,
+	ops::Not,
 	path::{Path, PathBuf},
+	process::ExitCode,
 	time::Duration,
 };
 
+/// Name of the virtual file with the source read from stdin.
+const STDIN_FILE: &str = "stdin.typ";
+
 #[derive(ValueEnum, Clone, Debug)]
 enum Task {
 	Check,
 	Watch,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Format {
+	/// Annotated source snippets.
+	#[default]
+	Human,
+	/// An array of objects with the location, rule and message of each finding.
+	Json,
+}
+
 #[derive(Parser, Debug)]
+#[clap(
+	after_help = "Exit codes: 0 without findings over `--max-warnings`, 1 with more findings, 2 for errors like a document failing to compile."
+)]
 struct Args {
 	task: Task,
 
-	/// File to check, may be a folder with `watch`.
-	#[clap(short, long, default_value = None)]
-	path: Option<PathBuf>,
+	/// Files or glob patterns like `src/**/*.typ` to check, may be a folder with `watch`.
+	/// `-` reads the source from stdin, as the file `stdin.typ` in the root.
+	#[clap(short, long, num_args = 1..)]
+	path: Vec<String>,
 
-	/// Main file for the document. Defaults to `path`.
+	/// Project root for absolute paths like `/lib.typ`.
+	/// Defaults to the folder of the main file.
 	#[clap(short, long, default_value = None)]
 	root: Option<PathBuf>,
 
+	/// Additional folders with fonts.
+	#[clap(long)]
+	font_path: Vec<PathBuf>,
+
 	/// Main file for the document.
-	/// Defaults to `path`.
+	/// Defaults to each of `path`.
 	#[clap(short, long, default_value = None)]
 	main: Option<PathBuf>,
 
@@ -41,14 +68,30 @@ struct Args {
 	#[clap(long, default_value_t = 0.1, id = "SECONDS")]
 	delay: f64,
 
-	/// Length in chars to seperate chunks
+	/// Length in chars to seperate chunks, 0 to never split
 	#[clap(long, default_value_t = 1000)]
 	chunk_size: usize,
 
+	/// Paragraph leading in em, should match `#set par(leading: ..)`.
+	#[clap(long, default_value_t = 0.65)]
+	leading: f64,
+
 	/// Print results without annotations for easy regex evaluation.
 	#[clap(long, default_value_t = false)]
 	plain: bool,
 
+	/// Number of findings still exiting with code 0.
+	#[clap(long, default_value_t = 0)]
+	max_warnings: usize,
+
+	/// Only count findings of these rule categories for the exit code, like `TYPOS`.
+	#[clap(long, value_delimiter = ',')]
+	fail_on: Vec<String>,
+
+	/// Output format of the results.
+	#[clap(long, value_enum, default_value_t = Format::Human)]
+	format: Format,
+
 	/// Use bundled languagetool jar.
 	#[clap(long, default_value_t = false)]
 	bundled: bool,
@@ -64,12 +107,29 @@ struct Args {
 	/// Port for remote languagetool server.
 	#[clap(long, default_value = None)]
 	port: Option<String>,
+
+	/// Folder to keep the results of unchanged text across runs in.
+	#[clap(long, default_value = None)]
+	cache_dir: Option<PathBuf>,
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
 	let args = Args::parse();
+	let max_warnings = args.max_warnings;
+	match run(args).await {
+		Ok(Some(findings)) if findings > max_warnings => ExitCode::from(1),
+		Ok(Some(_)) => ExitCode::SUCCESS,
+		Ok(None) => ExitCode::from(2),
+		Err(err) => {
+			eprintln!("Error: {:?}", err);
+			ExitCode::from(2)
+		},
+	}
+}
 
+/// Runs the task and returns the number of counted findings, `None` if the document failed to compile.
+async fn run(args: Args) -> anyhow::Result<Option<usize>> {
 	let lt = LanguageTool::new(
 		args.bundled,
 		args.jar_location.as_ref(),
@@ -77,12 +137,31 @@ async fn main() -> anyhow::Result<()> {
 		args.port.as_ref(),
 	)?;
 
-	let world = match (args.path.clone(), args.main.clone()) {
-		(_, Some(main)) => lt_world::LtWorld::new(main, args.root.clone()),
-		(Some(main), None) => lt_world::LtWorld::new(main, args.root.clone()),
+	let mut paths = expand(&args.path)?;
+	let stdin = match paths.iter_mut().find(|path| path.as_os_str() == "-") {
+		Some(path) => {
+			let root = match &args.root {
+				Some(root) => root.clone(),
+				None => std::env::current_dir()?,
+			};
+			*path = root.join(STDIN_FILE);
+			let mut text = String::new();
+			std::io::stdin().read_to_string(&mut text)?;
+			Some((path.clone(), text))
+		},
+		None => None,
+	};
+	let mut world = match (paths.first(), args.main.clone()) {
+		(_, Some(main)) => LtWorld::with_font_paths(main, args.root.clone(), &args.font_path),
+		(Some(main), None) => {
+			LtWorld::with_font_paths(main.clone(), args.root.clone(), &args.font_path)
+		},
 
 		_ => return Err(anyhow::anyhow!("Invalid typst settings.")),
 	};
+	if let Some((path, text)) = stdin {
+		world.use_shadow_file(&path, text);
+	}
 
 	// if let Some(path) = &args.dictionary {
 	// 	let content = std::fs::read_to_string(path)?;
@@ -95,122 +174,316 @@ async fn main() -> anyhow::Result<()> {
 	// lt.disable_checks(&args.disabled_checks).await?;
 
 	match args.task {
-		Task::Check => check(args, lt, world).await?,
-		Task::Watch => watch(args, lt, world).await?,
-	}
-
-	Ok(())
-}
-
-async fn check(args: Args, mut lt: LanguageTool, mut world: LtWorld) -> anyhow::Result<()> {
-	handle_file(
-		args.path.as_ref().unwrap(),
-		&mut lt,
-		&args,
-		&mut world,
-		args.chunk_size,
-		&mut Cache::new(),
-	)
-	.await?;
-	Ok(())
-}
-
-async fn watch(args: Args, mut lt: LanguageTool, mut world: LtWorld) -> anyhow::Result<()> {
-	let (tx, rx) = std::sync::mpsc::channel();
-	let mut watcher = new_debouncer(Duration::from_secs_f64(args.delay), tx)?;
-	let mut cache = Cache::new();
-	watcher
-		.watcher()
-		.watch(world.root(), RecursiveMode::Recursive)?;
-
-	for events in rx {
-		for event in events.unwrap() {
-			match event.path.extension() {
-				Some(ext) if ext == "typ" => {},
-				_ => continue,
+		Task::Check => check(args, paths, lt, world).await,
+		Task::Watch => watch(args, paths, lt, world).await,
+	}
+}
+
+/// Expands the glob patterns, other paths are kept as they are.
+fn expand(patterns: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+	let mut paths = Vec::new();
+	for pattern in patterns {
+		if pattern.contains(['*', '?', '[']).not() {
+			paths.push(PathBuf::from(pattern));
+			continue;
+		}
+		let mut matches = glob::glob(pattern)?.collect::<Result<Vec<_>, _>>()?;
+		matches.sort();
+		paths.extend(matches);
+	}
+	let mut unique = Vec::with_capacity(paths.len());
+	for path in paths {
+		if unique.contains(&path).not() {
+			unique.push(path);
+		}
+	}
+	Ok(unique)
+}
+
+/// Checks all files, each in the first compiled document containing its text.
+///
+/// Without an explicit main file every file not yet checked is compiled as the main file,
+/// so included files are only reported once.
+async fn check(
+	args: Args,
+	paths: Vec<PathBuf>,
+	mut lt: LanguageTool,
+	mut world: LtWorld,
+) -> anyhow::Result<Option<usize>> {
+	let root = world.root().to_path_buf();
+	let mut caches = Caches::new(&args, &mut lt).await?;
+	let mut reports = Vec::new();
+	let mut failed = false;
+	let mut remaining = paths.clone();
+	for path in &paths {
+		if remaining.contains(path).not() {
+			continue;
+		}
+		if args.main.is_none() {
+			world.update(path.clone(), Some(root.clone()));
+		}
+		let doc = compile(&world, &args);
+		failed |= doc.is_none();
+		let mut unchecked = Vec::new();
+		for other in remaining {
+			// without a document only the markup of the file itself is checked
+			if doc.is_none() && &other != path {
+				unchecked.push(other);
+				continue;
+			}
+			let checked = check_file(&other, doc.as_ref(), &mut lt, &args, &world, &mut caches);
+			match checked.await? {
+				Some(report) => reports.push(report),
+				None if &other == path => reports.push(Report::empty(&other, &world)?),
+				None => unchecked.push(other),
+			}
+		}
+		if args.main.is_some() {
+			// the files are not part of the only document
+			for other in unchecked {
+				reports.push(Report::empty(&other, &world)?);
 			}
+			break;
+		}
+		remaining = unchecked;
+	}
+	caches.evict()?;
+	let findings = reports.iter().map(|report| report.findings(&args)).sum();
+	print(&args, reports);
+	Ok((!failed).then_some(findings))
+}
+
+async fn watch(
+	args: Args,
+	paths: Vec<PathBuf>,
+	mut lt: LanguageTool,
+	world: LtWorld,
+) -> anyhow::Result<Option<usize>> {
+	let root = match paths.first() {
+		Some(path) if path.is_dir() => path.clone(),
+		_ => world.root().to_path_buf(),
+	};
+	let mut notifier = watch::FileNotifier::new(&root, Duration::from_secs_f64(args.delay))?;
+	let clear = args.plain.not() && args.format == Format::Human;
+	let caches = Caches::new(&args, &mut lt).await?;
+	let mut session = Session {
+		lt: &mut lt,
+		args: &args,
+		world: &world,
+		caches,
+	};
+	watch::run(&mut notifier, &mut session, clear).await?;
+	Ok(Some(0))
+}
+
+/// State kept between the checks of `watch`.
+struct Session<'a> {
+	lt: &'a mut LanguageTool,
+	args: &'a Args,
+	world: &'a LtWorld,
+	caches: Caches,
+}
+
+impl watch::Check for Session<'_> {
+	async fn check(&mut self, path: &Path) -> anyhow::Result<()> {
+		let doc = compile(self.world, self.args);
+		let checked = check_file(
+			path,
+			doc.as_ref(),
+			self.lt,
+			self.args,
+			self.world,
+			&mut self.caches,
+		);
+		let report = match checked.await? {
+			Some(report) => report,
+			None => Report::empty(path, self.world)?,
+		};
+		self.caches.finish();
+		print(self.args, vec![report]);
+		Ok(())
+	}
+}
+
+/// Suggestions of the last and the current check, and of earlier runs with `--cache-dir`.
+struct Caches {
+	last: CheckCache,
+	next: CheckCache,
+	disk: Option<DiskCache>,
+	/// Everything changing the results besides the text, like the languagetool jar.
+	rules: (&'static str, bool, Option<String>, Option<String>),
+}
+
+impl Caches {
+	/// Asks for the version of LanguageTool if results are kept across runs.
+	async fn new(args: &Args, lt: &mut LanguageTool) -> anyhow::Result<Self> {
+		let disk = args.cache_dir.as_ref().map(DiskCache::new).transpose()?;
+		let version = match disk {
+			Some(_) => Some(lt.version().await?),
+			None => None,
+		};
+		Ok(Self {
+			last: CheckCache::new(),
+			next: CheckCache::new(),
+			disk,
+			rules: (
+				env!("CARGO_PKG_VERSION"),
+				args.bundled,
+				args.jar_location.clone(),
+				version,
+			),
+		})
+	}
+
+	fn get(&self, lang: &str, text: &str) -> Option<Vec<Suggestion>> {
+		if let Some(suggestions) = self.last.get(lang, text, &self.rules) {
+			return Some(suggestions.to_vec());
+		}
+		self.disk.as_ref()?.get(lang, text, &self.rules)
+	}
+
+	/// Keeps the suggestions for the next check.
+	fn insert(&mut self, lang: &str, text: &str, suggestions: Vec<Suggestion>) {
+		self.next.insert(lang, text, &self.rules, suggestions);
+	}
 
-			handle_file(
-				&event.path,
-				&mut lt,
-				&args,
-				&mut world,
-				args.chunk_size,
-				&mut cache,
-			)
-			.await?;
+	/// Stores newly checked suggestions for later runs.
+	fn store(&self, lang: &str, text: &str, suggestions: &[Suggestion]) -> anyhow::Result<()> {
+		match &self.disk {
+			Some(disk) => disk.insert(lang, text, &self.rules, suggestions),
+			None => Ok(()),
+		}
+	}
+
+	/// Keeps the suggestions of the current check for the next one.
+	fn finish(&mut self) {
+		self.last = std::mem::take(&mut self.next);
+	}
+
+	fn evict(&self) -> anyhow::Result<()> {
+		match &self.disk {
+			Some(disk) => disk.evict(),
+			None => Ok(()),
 		}
 	}
-	Ok(())
 }
 
-async fn handle_file(
-	path: &Path,
-	lt: &mut LanguageTool,
-	args: &Args,
-	world: &LtWorld,
-	chunk_size: usize,
-	cache: &mut Cache,
-) -> anyhow::Result<()> {
-	let Some(doc) = world.compile() else {
-		if args.plain {
+/// Findings of a single file.
+struct Report {
+	path: PathBuf,
+	source: Source,
+	diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+	fn empty(path: &Path, world: &LtWorld) -> anyhow::Result<Self> {
+		Ok(Self {
+			path: path.into(),
+			source: world.source(world.file_id(path))?,
+			diagnostics: Vec::new(),
+		})
+	}
+
+	/// Number of findings counted for the exit code.
+	fn findings(&self, args: &Args) -> usize {
+		self.diagnostics
+			.iter()
+			.filter(|diagnostic| {
+				args.fail_on.is_empty() || args.fail_on.contains(&diagnostic.category)
+			})
+			.count()
+	}
+}
+
+fn compile(world: &LtWorld, args: &Args) -> Option<Document> {
+	let doc = world.compile();
+	if doc.is_none() {
+		if args.format == Format::Json {
+			eprintln!("Failed to compile document!");
+		} else if args.plain {
 			println!("Failed to compile document!");
 		} else {
 			println!("{}", "Failed to compile document!\n".red().bold());
 		}
-		return Ok(());
-	};
+	}
+	doc
+}
 
+/// Checks the text of the file in the document, `None` if the document does not contain it.
+///
+/// The main file is checked by its markup if it failed to compile or has no text in the layout.
+async fn check_file(
+	path: &Path,
+	doc: Option<&Document>,
+	lt: &mut LanguageTool,
+	args: &Args,
+	world: &LtWorld,
+	caches: &mut Caches,
+) -> anyhow::Result<Option<Report>> {
 	let file_id = world.file_id(path);
-	let paragraphs = typst_languagetool::convert::document(&doc, chunk_size, file_id);
-	let mut collector = typst_languagetool::FileCollector::new(file_id, world);
-	let mut next_cache = Cache::new();
+	let source = world.source(file_id)?;
+	let options = ConvertOptions {
+		leading: Em::new(args.leading),
+		..Default::default()
+	};
+	let paragraphs = match doc {
+		// other files are only checked if the document contains their text
+		Some(doc) if file_id != world.main().id() => {
+			convert::document(doc, args.chunk_size, &source, &options)?
+		},
+		doc => convert::document_or_markup(doc, args.chunk_size, &source, &options)?,
+	};
+	if paragraphs.is_empty() {
+		return Ok(None);
+	}
+	let mut collector = typst_languagetool::FileCollector::new(file_id, world)?;
 	for (text, mapping) in paragraphs {
 		let lang = mapping.long_language();
-		let suggestions = if let Some(suggestions) = cache.get(&text) {
-			suggestions
-		} else {
-			lt.check_text(lang, &text).await?
+		let suggestions = match caches.get(&lang, &text) {
+			Some(suggestions) => suggestions,
+			None => {
+				let suggestions = lt.check_text(lang.clone(), &text).await?;
+				caches.store(&lang, &text, &suggestions)?;
+				suggestions
+			},
 		};
 
 		collector.add(&suggestions, mapping);
-		next_cache.insert(text, suggestions);
+		caches.insert(&lang, &text, suggestions);
 	}
-	*cache = next_cache;
 
 	let (source, diagnostics) = collector.finish();
+	Ok(Some(Report { path: path.into(), source, diagnostics }))
+}
 
-	if args.plain {
+/// Prints the findings grouped by file.
+fn print(args: &Args, reports: Vec<Report>) {
+	if args.format == Format::Json {
+		let diagnostics = reports
+			.into_iter()
+			.flat_map(|report| {
+				match output::json(&report.path, &report.source, report.diagnostics) {
+					Value::Array(diagnostics) => diagnostics,
+					_ => Vec::new(),
+				}
+			})
+			.collect();
+		println!("{}", Value::Array(diagnostics));
+	} else if args.plain {
 		println!("START");
-		for diagnostic in diagnostics {
-			output::plain(&path, &source, diagnostic);
+		for report in reports {
+			for diagnostic in report.diagnostics {
+				output::plain(&report.path, &report.source, diagnostic);
+			}
 		}
 		println!("END");
 	} else {
-		println!("{}", "\n\nChecking Document\n".green().bold());
-		for diagnostic in diagnostics {
-			output::pretty(&path, &source, diagnostic);
+		for report in reports {
+			let title = format!("\n\nChecking {}\n", report.path.display());
+			println!("{}", title.green().bold());
+			for diagnostic in report.diagnostics {
+				output::pretty(&report.path, &report.source, diagnostic);
+			}
 		}
 	}
-
-	Ok(())
-}
-
-#[derive(Debug)]
-struct Cache {
-	cache: HashMap<String, Vec<Suggestion>>,
-}
-
-impl Cache {
-	pub fn new() -> Self {
-		Self { cache: HashMap::new() }
-	}
-
-	pub fn get(&mut self, text: &str) -> Option<Vec<Suggestion>> {
-		self.cache.remove(text)
-	}
-
-	pub fn insert(&mut self, text: String, suggestions: Vec<Suggestion>) {
-		self.cache.insert(text, suggestions);
-	}
 }
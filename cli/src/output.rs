@@ -1,16 +1,19 @@
 use std::{io::stdout, io::Write, ops::Not, path::Path};
 
 use annotate_snippets::{Level, Renderer, Snippet};
+use serde_json::{json, Value};
 use typst::syntax::Source;
-use typst_languagetool::Diagnostic;
+use typst_languagetool::{line_column, Diagnostic};
 
 const MAX_SUGGESTIONS: usize = 20;
+const TAB: &str = "    ";
 
 pub fn plain(file: &Path, source: &Source, diagnostic: Diagnostic) {
 	let mut out = stdout().lock();
 
-	let (start_line, start_column) = byte_to_position(source, diagnostic.locations[0].start);
-	let (end_line, end_column) = byte_to_position(source, diagnostic.locations[0].end);
+	// locations outside of the source are reported at its start
+	let ((start_line, start_column), (end_line, end_column)) =
+		line_column(source, diagnostic.locations[0].clone()).unwrap_or_default();
 	write!(
 		out,
 		"{} {}:{}-{}:{} info {}",
@@ -40,28 +43,64 @@ pub fn plain(file: &Path, source: &Source, diagnostic: Diagnostic) {
 }
 
 pub fn pretty(file: &Path, source: &Source, diagnostic: Diagnostic) {
+	if let Some(rendered) = render(file, source, &diagnostic, Renderer::styled()) {
+		println!("{}", rendered);
+	}
+}
+
+/// Renders the lines of the diagnostic with every location underlined.
+///
+/// `None` if a location is outside of the source, as there are no lines to show.
+fn render(
+	file: &Path,
+	source: &Source,
+	diagnostic: &Diagnostic,
+	renderer: Renderer,
+) -> Option<String> {
 	let file_name = format!("{}", file.display());
 
-	let (start_line, _) = byte_to_position(source, diagnostic.locations[0].start);
-	let (end_line, _) = byte_to_position(source, diagnostic.locations[0].end);
+	let first = diagnostic.locations[0].clone();
+	let last = diagnostic.locations[diagnostic.locations.len() - 1].clone();
+	if diagnostic
+		.locations
+		.iter()
+		.any(|location| line_column(source, location.clone()).is_none())
+	{
+		return None;
+	}
+	let ((start_line, _), _) = line_column(source, first.clone())?;
+	let (_, (end_line, _)) = line_column(source, last)?;
 	let text = source.text();
 	let context = if start_line == end_line {
-		source.line_to_range(start_line).unwrap()
+		source.line_to_range(start_line)?
 	} else {
-		let start = source.line_to_byte(start_line).unwrap();
+		let start = source.line_to_byte(start_line)?;
 		let end = source.line_to_byte(end_line + 1).unwrap_or(text.len());
 		start..end
 	};
 
-	let mut snippet = Snippet::source(&text[context.clone()])
+	// tabs are expanded here, so the underlines stay aligned with the text
+	let snippet_text = text[context.clone()].replace('\t', TAB);
+	let offset = |index: usize| {
+		let tabs = text[context.start..index].matches('\t').count();
+		index - context.start + tabs * (TAB.len() - 1)
+	};
+
+	let mut snippet = Snippet::source(&snippet_text)
 		.line_start(start_line + 1)
 		.origin(&file_name)
 		.fold(true);
 
-	let start = diagnostic.locations[0].start - context.start;
-	let end = diagnostic.locations[0].end - context.start;
-
-	snippet = snippet.annotation(Level::Info.span(start..end).label(&diagnostic.message));
+	let end = offset(first.end);
+	snippet = snippet.annotation(
+		Level::Info
+			.span(offset(first.start)..end)
+			.label(&diagnostic.message),
+	);
+	for location in &diagnostic.locations[1..] {
+		snippet =
+			snippet.annotation(Level::Info.span(offset(location.start)..offset(location.end)));
+	}
 
 	for replacement in diagnostic
 		.replacements
@@ -69,21 +108,135 @@ pub fn pretty(file: &Path, source: &Source, diagnostic: Diagnostic) {
 		.filter(|replacement| replacement.trim().is_empty().not())
 		.take(MAX_SUGGESTIONS)
 	{
-		snippet = snippet.annotation(Level::Help.span(end..end).label(&replacement));
+		snippet = snippet.annotation(Level::Help.span(end..end).label(replacement));
 	}
 	let message = Level::Info
 		.title(&diagnostic.rule_description)
 		.id(&diagnostic.rule_id)
 		.snippet(snippet);
 
-	let renderer = Renderer::styled();
-	println!("{}", renderer.render(message));
+	let rendered = renderer.render(message).to_string();
+	Some(rendered)
 }
 
-fn byte_to_position(source: &Source, index: usize) -> (usize, usize) {
-	let line = source.byte_to_line(index).unwrap();
-	let start = source.line_to_byte(line).unwrap();
-	let head = source.get(start..index).unwrap();
-	let column = head.chars().count();
-	(line, column)
+pub fn json(file: &Path, source: &Source, diagnostics: Vec<Diagnostic>) -> Value {
+	let diagnostics = diagnostics
+		.into_iter()
+		.map(|diagnostic| {
+			let range = diagnostic.locations[0].clone();
+			// locations outside of the source are reported at its start
+			let ((line, column), _) = line_column(source, range.clone()).unwrap_or_default();
+			json!({
+				"file": file.display().to_string(),
+				"start": range.start,
+				"end": range.end,
+				"line": line + 1,
+				"column": column + 1,
+				"rule_id": diagnostic.rule_id,
+				"category": diagnostic.category,
+				"message": diagnostic.message,
+				"replacements": diagnostic.replacements,
+			})
+		})
+		.collect();
+	Value::Array(diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+	use typst::syntax::{FileId, VirtualPath};
+
+	use super::*;
+
+	#[test]
+	fn test_render() {
+		let id = FileId::new(None, VirtualPath::new("main.typ"));
+		let source = Source::new(id, "= Title\n\tA tpyo and anothr one.\n".into());
+		let location = |word: &str| {
+			let start = source.text().find(word).unwrap();
+			start..start + word.len()
+		};
+		let diagnostic = Diagnostic {
+			locations: vec![location("tpyo"), location("anothr")],
+			message: "Possible spelling mistakes found.".into(),
+			short_message: "Spelling mistake".into(),
+			replacements: Vec::new(),
+			rule_description: "Possible Typo".into(),
+			rule_id: "MORFOLOGIK_RULE_EN_US".into(),
+			category: "TYPOS".into(),
+			long: false,
+		};
+		let rendered = render(
+			Path::new("main.typ"),
+			&source,
+			&diagnostic,
+			Renderer::plain(),
+		)
+		.unwrap();
+		let lines = rendered.lines().collect::<Vec<_>>();
+		let line = lines.iter().position(|line| line.contains("tpyo")).unwrap();
+		let text = lines[line];
+		assert!(text.contains("    A tpyo"));
+		// labels can move later underlines to the next lines
+		let mut underlined = lines[line + 1..]
+			.iter()
+			.flat_map(|underline| underline.char_indices().filter(|(_, c)| *c == '-'))
+			.map(|(index, _)| index)
+			.collect::<Vec<_>>();
+		underlined.sort();
+		let tpyo = text.find("tpyo").unwrap();
+		let anothr = text.find("anothr").unwrap();
+		assert_eq!(
+			underlined,
+			(tpyo..tpyo + 4)
+				.chain(anothr..anothr + 6)
+				.collect::<Vec<_>>()
+		);
+	}
+
+	#[test]
+	fn test_json() {
+		let id = FileId::new(None, VirtualPath::new("main.typ"));
+		let source = Source::new(id, "= Title\nThis is a tpyo.".into());
+		let start = source.text().find("tpyo").unwrap();
+		let location = start..start + 4;
+		let diagnostic = Diagnostic {
+			locations: vec![location],
+			message: "Possible spelling mistake found.".into(),
+			short_message: "Spelling mistake".into(),
+			replacements: vec!["typo".into(), "typos".into()],
+			rule_description: "Possible Typo".into(),
+			rule_id: "MORFOLOGIK_RULE_EN_US".into(),
+			category: "TYPOS".into(),
+			long: false,
+		};
+		let json = json(Path::new("main.typ"), &source, vec![diagnostic]);
+		assert_eq!(
+			json.to_string(),
+			r#"[{"category":"TYPOS","column":11,"end":22,"file":"main.typ","line":2,"message":"Possible spelling mistake found.","replacements":["typo","typos"],"rule_id":"MORFOLOGIK_RULE_EN_US","start":18}]"#
+		);
+	}
+
+	#[test]
+	fn test_outside_of_source() {
+		let id = FileId::new(None, VirtualPath::new("main.typ"));
+		let source = Source::new(id, "A tpyo.".into());
+		let diagnostic = Diagnostic {
+			locations: vec![20..24, 2..6],
+			message: "Possible spelling mistake found.".into(),
+			short_message: String::new(),
+			replacements: Vec::new(),
+			rule_description: String::new(),
+			rule_id: String::new(),
+			category: String::new(),
+			long: false,
+		};
+		let path = Path::new("main.typ");
+		assert!(render(path, &source, &diagnostic, Renderer::plain()).is_none());
+		let json = json(path, &source, vec![diagnostic]);
+		assert_eq!(
+			(&json[0]["line"], &json[0]["column"]),
+			(&1.into(), &1.into())
+		);
+	}
 }
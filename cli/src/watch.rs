@@ -0,0 +1,149 @@
+use std::{
+	path::{Path, PathBuf},
+	sync::mpsc::{channel, Receiver},
+	time::Duration,
+};
+
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+
+/// Source of changed files.
+pub trait Notifier {
+	/// Waits for the next changes, `None` once no more changes can occur.
+	fn next_changes(&mut self) -> Option<Vec<PathBuf>>;
+}
+
+/// Check of a single changed file.
+pub trait Check {
+	async fn check(&mut self, path: &Path) -> anyhow::Result<()>;
+}
+
+/// Notifies about changes of the files in a folder.
+pub struct FileNotifier {
+	rx: Receiver<DebounceEventResult>,
+	_debouncer: Debouncer<RecommendedWatcher>,
+}
+
+impl FileNotifier {
+	pub fn new(root: &Path, delay: Duration) -> anyhow::Result<Self> {
+		let (tx, rx) = channel();
+		let mut debouncer = new_debouncer(delay, tx)?;
+		debouncer.watcher().watch(root, RecursiveMode::Recursive)?;
+		Ok(Self { rx, _debouncer: debouncer })
+	}
+}
+
+impl Notifier for FileNotifier {
+	fn next_changes(&mut self) -> Option<Vec<PathBuf>> {
+		for events in self.rx.iter() {
+			let Ok(events) = events else {
+				continue;
+			};
+			return Some(events.into_iter().map(|event| event.path).collect());
+		}
+		None
+	}
+}
+
+/// Checks every changed typst file, until the notifier stops.
+///
+/// With `clear` the terminal is cleared before the results of each run.
+pub async fn run(
+	notifier: &mut impl Notifier,
+	checker: &mut impl Check,
+	clear: bool,
+) -> anyhow::Result<()> {
+	while let Some(paths) = notifier.next_changes() {
+		let paths = typst_files(paths);
+		if paths.is_empty() {
+			continue;
+		}
+		if clear {
+			print!("\x1B[2J\x1B[1;1H");
+		}
+		for path in paths {
+			checker.check(&path).await?;
+		}
+	}
+	Ok(())
+}
+
+/// The typst files of the paths, each only once.
+fn typst_files(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+	let mut files = Vec::new();
+	for path in paths {
+		match path.extension() {
+			Some(ext) if ext == "typ" => {},
+			_ => continue,
+		}
+		if files.contains(&path) {
+			continue;
+		}
+		files.push(path);
+	}
+	files
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::VecDeque;
+
+	use super::*;
+
+	struct Changes(VecDeque<Vec<PathBuf>>);
+
+	impl Notifier for Changes {
+		fn next_changes(&mut self) -> Option<Vec<PathBuf>> {
+			self.0.pop_front()
+		}
+	}
+
+	#[derive(Default)]
+	struct Checked(Vec<PathBuf>);
+
+	impl Check for Checked {
+		async fn check(&mut self, path: &Path) -> anyhow::Result<()> {
+			self.0.push(path.into());
+			Ok(())
+		}
+	}
+
+	#[tokio::test]
+	async fn test_run() {
+		let mut changes = Changes(VecDeque::from([
+			vec!["main.typ".into(), "main.typ".into()],
+			vec!["image.png".into()],
+			vec!["chapter.typ".into(), "main.typ".into()],
+		]));
+		let mut checked = Checked::default();
+		run(&mut changes, &mut checked, false).await.unwrap();
+		assert_eq!(
+			checked.0,
+			vec![
+				PathBuf::from("main.typ"),
+				PathBuf::from("chapter.typ"),
+				PathBuf::from("main.typ")
+			]
+		);
+	}
+
+	#[test]
+	fn test_file_notifier() {
+		let root =
+			std::env::temp_dir().join(format!("typst-languagetool-watch-{}", std::process::id()));
+		std::fs::create_dir_all(&root).unwrap();
+		let root = root.canonicalize().unwrap();
+		let mut notifier = FileNotifier::new(&root, Duration::from_millis(50)).unwrap();
+		let file = root.join("main.typ");
+		std::fs::write(&file, "Some text.").unwrap();
+
+		// fail instead of hanging if the watcher misses the change
+		let (tx, rx) = channel();
+		std::thread::spawn(move || tx.send(notifier.next_changes()));
+		let changes = rx
+			.recv_timeout(Duration::from_secs(10))
+			.expect("no changes within 10 seconds");
+		std::fs::remove_dir_all(&root).unwrap();
+		assert!(changes.unwrap().contains(&file));
+	}
+}
@@ -0,0 +1,167 @@
+#![cfg(feature = "remote-server")]
+
+use std::{
+	io::Write,
+	path::{Path, PathBuf},
+	process::{Command, Output, Stdio},
+};
+
+/// Serves check requests, with a typo match for every `tpyo` in the text.
+fn mock_server() -> u16 {
+	lt_mock::typo_server().0
+}
+
+/// Writes the document into a temporary folder for the test.
+fn document(test: &str, name: &str, text: &str) -> PathBuf {
+	let root = std::env::temp_dir()
+		.join("typst-languagetool-cli")
+		.join(test);
+	std::fs::create_dir_all(&root).unwrap();
+	let path = root.canonicalize().unwrap().join(name);
+	std::fs::write(&path, text).unwrap();
+	path
+}
+
+fn check(path: &Path, port: u16, args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_typst-languagetool"))
+		.args(["check", "--plain", "--host", "http://127.0.0.1"])
+		.args(["--port", &port.to_string()])
+		.arg("--path")
+		.arg(path)
+		.args(args)
+		.output()
+		.unwrap()
+}
+
+fn exit_code(path: &Path, port: u16, args: &[&str]) -> i32 {
+	check(path, port, args).status.code().unwrap()
+}
+
+#[test]
+fn test_exit_code() {
+	let port = mock_server();
+	let clean = document("exit_code", "clean.typ", "Some clean text.");
+	let dirty = document("exit_code", "dirty.typ", "tpyo in some text.");
+	let broken = document("exit_code", "broken.typ", "#unknown-function()");
+
+	assert_eq!(exit_code(&clean, port, &[]), 0);
+	assert_eq!(exit_code(&dirty, port, &[]), 1);
+	assert_eq!(exit_code(&dirty, port, &["--max-warnings", "1"]), 0);
+	assert_eq!(exit_code(&dirty, port, &["--fail-on", "GRAMMAR"]), 0);
+	assert_eq!(exit_code(&dirty, port, &["--fail-on", "GRAMMAR,TYPOS"]), 1);
+	assert_eq!(exit_code(&broken, port, &[]), 2);
+}
+
+#[test]
+fn test_multiple_files() {
+	let port = mock_server();
+	let book = document("multiple_files", "book.typ", "#include \"chapter.typ\"");
+	document("multiple_files", "chapter.typ", "tpyo in the chapter.");
+	document("multiple_files", "notes.typ", "tpyo in the notes.");
+	let pattern = book.with_file_name("*.typ");
+	let output = check(&pattern, port, &[]);
+	assert_eq!(output.status.code(), Some(1));
+	let output = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(
+		output
+			.lines()
+			.filter(|line| line.contains("book.typ"))
+			.count(),
+		0
+	);
+	assert_eq!(
+		output
+			.lines()
+			.filter(|line| line.contains("chapter.typ"))
+			.count(),
+		1
+	);
+	assert_eq!(
+		output
+			.lines()
+			.filter(|line| line.contains("notes.typ"))
+			.count(),
+		1
+	);
+}
+
+#[test]
+fn test_root() {
+	let port = mock_server();
+	let main = document(
+		"root/chapter",
+		"main.typ",
+		"#import \"/lib.typ\": title\n#title\n\nA tpyo.",
+	);
+	let root = document("root", "lib.typ", "#let title = [= Chapter]");
+	let root = root.parent().unwrap().to_str().unwrap();
+	assert_eq!(exit_code(&main, port, &[]), 2);
+	let output = check(&main, port, &["--root", root]);
+	assert_eq!(output.status.code(), Some(1));
+	let output = String::from_utf8(output.stdout).unwrap();
+	assert!(output.contains("main.typ 4:3-4:7 info Possible typo"));
+}
+
+#[test]
+fn test_compile_failure() {
+	let port = mock_server();
+	let path = document(
+		"compile_failure",
+		"main.typ",
+		"A tpyo.\n\n#image(\"missing.png\")",
+	);
+	let output = check(&path, port, &[]);
+	assert_eq!(output.status.code(), Some(2));
+	let output = String::from_utf8(output.stdout).unwrap();
+	assert!(output.contains("Failed to compile document!"));
+	assert!(output.contains("main.typ 1:3-1:7 info Possible typo"));
+}
+
+#[test]
+fn test_stdin() {
+	let port = mock_server();
+	let root = document("stdin", "lib.typ", "");
+	let mut child = Command::new(env!("CARGO_BIN_EXE_typst-languagetool"))
+		.args(["check", "--format", "json", "--host", "http://127.0.0.1"])
+		.args(["--port", &port.to_string(), "--path", "-", "--root"])
+		.arg(root.parent().unwrap())
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.spawn()
+		.unwrap();
+	let mut stdin = child.stdin.take().unwrap();
+	stdin
+		.write_all("= Title\nHere is a tpyo.".as_bytes())
+		.unwrap();
+	drop(stdin);
+	let output = child.wait_with_output().unwrap();
+	assert_eq!(output.status.code(), Some(1));
+	let output: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(output[0]["start"], 18);
+	assert_eq!(output[0]["end"], 22);
+	assert!(output[0]["file"].as_str().unwrap().ends_with("stdin.typ"));
+}
+
+#[test]
+fn test_cache_dir() {
+	let port = mock_server();
+	let path = document("cache_dir", "main.typ", "A tpyo in the text.");
+	let cache = path.with_file_name("cache");
+	let _ = std::fs::remove_dir_all(&cache);
+	let cache = cache.to_str().unwrap();
+	let first = check(&path, port, &["--cache-dir", cache]);
+	assert_eq!(first.status.code(), Some(1));
+
+	// only the version is asked for
+	let (port, requests) = lt_mock::typo_server_with_version("6.4");
+	let second = check(&path, port, &["--cache-dir", cache]);
+	assert_eq!(second.status.code(), Some(1));
+	assert_eq!(second.stdout, first.stdout);
+	assert_eq!(requests.lock().unwrap().len(), 1);
+
+	// an upgraded server checks the text again
+	let (port, requests) = lt_mock::typo_server_with_version("6.5");
+	let third = check(&path, port, &["--cache-dir", cache]);
+	assert_eq!(third.status.code(), Some(1));
+	assert_eq!(requests.lock().unwrap().len(), 2);
+}
@@ -9,8 +9,8 @@ use lsp_types::request::*;
 use lsp_types::*;
 use lt_world::LtWorld;
 use serde_json::Value;
-use typst::syntax::Source;
-use typst_languagetool::{LanguageTool, LanguageToolBackend, Suggestion};
+use typst::{syntax::Source, World};
+use typst_languagetool::{convert::ConvertOptions, CheckCache, LanguageTool, LanguageToolBackend};
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(default)]
@@ -19,6 +19,8 @@ struct InitOptions {
 	dictionary: HashMap<String, Vec<String>>,
 	/// Languagetool rules to ignore (WHITESPACE_RULE, ...)
 	disabled_checks: HashMap<String, Vec<String>>,
+	/// Languagetool rules to use in addition to the default rules
+	enabled_checks: HashMap<String, Vec<String>>,
 
 	languages: Vec<String>,
 
@@ -31,7 +33,7 @@ struct InitOptions {
 	/// port for remote languagetool
 	port: Option<String>,
 
-	/// Size for chunk send to LanguageTool
+	/// Size for chunk send to LanguageTool, 0 to never split
 	chunk_size: usize,
 	/// Duration to wait for additional changes before checking the file
 	/// Leave empty to only check on open and save
@@ -49,6 +51,7 @@ impl Default for InitOptions {
 		Self {
 			dictionary: HashMap::new(),
 			disabled_checks: HashMap::new(),
+			enabled_checks: HashMap::new(),
 			languages: Vec::new(),
 
 			bundled: false,
@@ -86,6 +89,9 @@ impl InitOptions {
 		for (lang, checks) in &self.disabled_checks {
 			lt.disable_checks(lang.clone(), checks).await?;
 		}
+		for (lang, checks) in &self.enabled_checks {
+			lt.enable_checks(lang.clone(), checks).await?;
+		}
 		Ok(lt)
 	}
 
@@ -109,7 +115,15 @@ async fn main() -> anyhow::Result<()> {
 	eprintln!("starting LSP server");
 
 	let (connection, io_threads) = Connection::stdio();
+	serve(connection).await?;
+	io_threads.join()?;
 
+	eprintln!("shutting down server");
+	Ok(())
+}
+
+/// Answers the requests of the client until it shuts the server down.
+async fn serve(connection: Connection) -> anyhow::Result<()> {
 	let capabilities = ServerCapabilities {
 		text_document_sync: Some(TextDocumentSyncCapability::Options(
 			TextDocumentSyncOptions {
@@ -123,25 +137,20 @@ async fn main() -> anyhow::Result<()> {
 		)),
 
 		code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+		hover_provider: Some(HoverProviderCapability::Simple(true)),
+		diagnostic_provider: Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
+			identifier: Some("typst-languagetool".into()),
+			inter_file_dependencies: true,
+			workspace_diagnostics: false,
+			..Default::default()
+		})),
 		..Default::default()
 	};
 
 	let server_capabilities = serde_json::to_value(capabilities).unwrap();
-	let initialization_params = match connection.initialize(server_capabilities) {
-		Ok(it) => it,
-		Err(e) => {
-			if e.channel_is_disconnected() {
-				io_threads.join()?;
-			}
-			return Err(e.into());
-		},
-	};
+	let initialization_params = connection.initialize(server_capabilities)?;
 	let state = State::new(connection, initialization_params).await?;
-	state.main_loop().await?;
-	io_threads.join()?;
-
-	eprintln!("shutting down server");
-	Ok(())
+	state.main_loop().await
 }
 
 struct Options {
@@ -152,11 +161,13 @@ struct Options {
 
 struct State {
 	world: LtWorld,
-	cache: Cache,
+	cache: CheckCache,
 	lt: LanguageTool,
 	connection: Connection,
 	check: Option<CheckData>,
 	options: Options,
+	/// Findings of the last check of each file, with their range.
+	findings: HashMap<PathBuf, Vec<(Range, typst_languagetool::Diagnostic)>>,
 }
 
 struct CheckData {
@@ -179,13 +190,13 @@ impl State {
 			eprintln!("unknown option: {}", path);
 		})?;
 
-		let cache = Cache::new();
+		let cache = CheckCache::new();
 
 		options.make_absolute();
 		eprintln!("options: {:#?}", options);
 		let lt = options.create_lt().await?;
 		let Some(main) = &options.main else {
-			return Err(anyhow::anyhow!("main file is required")).unwrap();
+			return Err(anyhow::anyhow!("main file is required"));
 		};
 
 		let world = lt_world::LtWorld::new(main.clone(), options.root);
@@ -201,6 +212,7 @@ impl State {
 			lt,
 			connection,
 			check: None,
+			findings: HashMap::new(),
 
 			options: Options {
 				on_change: options.on_change,
@@ -214,6 +226,11 @@ impl State {
 		eprintln!("waiting for events");
 		loop {
 			match self.next_action()? {
+				Action::Message(Message::Request(req))
+					if self.connection.handle_shutdown(&req)? =>
+				{
+					return Ok(());
+				},
 				Action::Message(msg) => self.message(msg).await?,
 				Action::Check(data) => self.check_change(&data.path, data.url).await?,
 			}
@@ -239,12 +256,7 @@ impl State {
 
 	pub async fn message(&mut self, msg: Message) -> anyhow::Result<()> {
 		match msg {
-			Message::Request(req) => {
-				if self.connection.handle_shutdown(&req)? {
-					return Ok(());
-				}
-				self.request(req).await
-			},
+			Message::Request(req) => self.request(req).await,
 			Message::Response(resp) => {
 				eprintln!("unknown response: {:?}", resp);
 				Ok(())
@@ -263,10 +275,78 @@ impl State {
 			Err(err @ ExtractError::JsonError { .. }) => return Err(err.into()),
 			Err(ExtractError::MethodMismatch(req)) => req,
 		};
+		let req = match cast_request::<DocumentDiagnosticRequest>(req) {
+			Ok((id, params)) => {
+				let report = self.document_diagnostic(params).await?;
+				send_response::<DocumentDiagnosticRequest>(&self.connection, id, report)?;
+				return Ok(());
+			},
+			Err(err @ ExtractError::JsonError { .. }) => return Err(err.into()),
+			Err(ExtractError::MethodMismatch(req)) => req,
+		};
+		let req = match cast_request::<HoverRequest>(req) {
+			Ok((id, params)) => {
+				let hover = self.hover(params);
+				send_response::<HoverRequest>(&self.connection, id, hover)?;
+				return Ok(());
+			},
+			Err(err @ ExtractError::JsonError { .. }) => return Err(err.into()),
+			Err(ExtractError::MethodMismatch(req)) => req,
+		};
 		eprintln!("unknown request: {:?}", req);
 		Ok(())
 	}
 
+	async fn document_diagnostic(
+		&mut self,
+		params: DocumentDiagnosticParams,
+	) -> anyhow::Result<DocumentDiagnosticReportResult> {
+		let path = params.text_document.uri.to_file_path().unwrap();
+		eprintln!("Diagnostics for {}", path.display());
+		let items = match self.get_diagnostics(&path).await {
+			Ok(items) => items,
+			Err(err) => {
+				eprintln!("{:?}", err);
+				Vec::new()
+			},
+		};
+		let report = FullDocumentDiagnosticReport { result_id: None, items };
+		Ok(DocumentDiagnosticReportResult::Report(
+			DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+				related_documents: None,
+				full_document_diagnostic_report: report,
+			}),
+		))
+	}
+
+	fn hover(&self, params: HoverParams) -> Option<Hover> {
+		let params = params.text_document_position_params;
+		let path = params.text_document.uri.to_file_path().unwrap();
+		let position = params.position;
+		let (range, diagnostic) = self
+			.findings
+			.get(&path)?
+			.iter()
+			.find(|(range, _)| range.start <= position && position <= range.end)?;
+
+		let title = match diagnostic.short_message.as_str() {
+			"" => &diagnostic.message,
+			short => short,
+		};
+		let value = format!(
+			"**{}**\n\n{}\n\n`{}` ({}): {}",
+			title,
+			diagnostic.message,
+			diagnostic.rule_id,
+			diagnostic.category,
+			diagnostic.rule_description
+		);
+		Some(Hover {
+			contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+			range: Some(*range),
+		})
+	}
+
 	async fn code_action(
 		&self,
 		params: CodeActionParams,
@@ -280,7 +360,7 @@ impl State {
 			return Ok(None);
 		};
 
-		let replacements = match serde_json::from_value::<Vec<String>>(data.clone()) {
+		let edits = match serde_json::from_value::<Vec<TextEdit>>(data.clone()) {
 			Ok(r) => r,
 			Err(err) => {
 				eprintln!("{}", err);
@@ -288,9 +368,8 @@ impl State {
 			},
 		};
 
-		for (i, value) in replacements.into_iter().enumerate() {
-			let title = format!("Replace with \"{}\"", value);
-			let replace = TextEdit { range: diagnostic.range, new_text: value };
+		for (i, replace) in edits.into_iter().enumerate() {
+			let title = replace.new_text.clone();
 			let edit = [(params.text_document.uri.clone(), vec![replace])]
 				.into_iter()
 				.collect();
@@ -381,7 +460,7 @@ impl State {
 	async fn file_close(&mut self, params: DidCloseTextDocumentParams) -> anyhow::Result<()> {
 		let path = &params.text_document.uri.to_file_path().unwrap();
 		eprintln!("Close {}", path.display());
-		self.world.use_original_file(&path);
+		self.world.use_original_file(path);
 		Ok(())
 	}
 
@@ -457,6 +536,8 @@ impl State {
 		if let Some(main) = options.main {
 			self.world.update(main, options.root);
 		}
+		// the cached suggestions were checked with the old rules and dictionary
+		self.cache = CheckCache::new();
 
 		self.options = Options {
 			on_change: options.on_change,
@@ -468,67 +549,82 @@ impl State {
 	}
 
 	async fn get_diagnostics(&mut self, path: &Path) -> anyhow::Result<Vec<Diagnostic>> {
-		let Some(doc) = self.world.compile() else {
-			eprintln!("TODO: Warning could not compile");
-			return Ok(Vec::new());
-		};
+		let doc = self.world.compile();
+		if doc.is_none() {
+			eprintln!("failed to compile document, checking the markup");
+		}
 
 		let file_id = self.world.file_id(path);
-		let paragraphs =
-			typst_languagetool::convert::document(&doc, self.options.chunk_size, file_id);
-		let mut collector = typst_languagetool::FileCollector::new(file_id, &self.world);
-		let mut next_cache = Cache::new();
+		let source = self.world.source(file_id)?;
+		let paragraphs = typst_languagetool::convert::document_or_markup(
+			doc.as_ref(),
+			self.options.chunk_size,
+			&source,
+			&ConvertOptions::default(),
+		)?;
+		let mut collector = typst_languagetool::FileCollector::new(file_id, &self.world)?;
+		let mut next_cache = CheckCache::new();
 		let l = paragraphs.len();
 		for (idx, (text, mapping)) in paragraphs.into_iter().enumerate() {
 			let lang = self
 				.options
 				.language_codes
 				.get(mapping.short_language())
-				.map(|x| x.clone())
+				.cloned()
 				.unwrap_or(mapping.long_language());
-			let suggestions = if let Some(suggestions) = self.cache.get(&text) {
-				suggestions
-			} else {
-				eprintln!("Checking {}/{}", idx + 1, l);
-				self.lt.check_text(lang, &text).await?
+			// the cache is cleared when the rules change
+			let rules = ();
+			let suggestions = match self.cache.get(&lang, &text, &rules) {
+				Some(suggestions) => suggestions.to_vec(),
+				None => {
+					eprintln!("Checking {}/{}", idx + 1, l);
+					self.lt.check_text(lang.clone(), &text).await?
+				},
 			};
 			collector.add(&suggestions, mapping);
-			next_cache.insert(text, suggestions);
+			next_cache.insert(&lang, &text, &rules, suggestions);
 		}
 		self.cache = next_cache;
 		eprintln!("Generating diagnostics");
 
 		let (source, diagnostics) = collector.finish();
 
-		let diagnostics = diagnostics
+		let findings = diagnostics
 			.into_iter()
 			.map(|diagnostic| {
-				let (start_line, start_column) =
-					byte_to_position(&source, diagnostic.locations[0].start);
-				let (end_line, end_column) = byte_to_position(&source, diagnostic.locations[0].end);
+				let range = byte_range_to_range(&source, diagnostic.locations[0].clone());
+				(range, diagnostic)
+			})
+			.collect::<Vec<_>>();
+
+		let diagnostics = findings
+			.iter()
+			.map(|(range, diagnostic)| {
+				// replacements of disjoint locations can not be applied as a single edit
+				let edits = diagnostic
+					.replacements
+					.iter()
+					.filter_map(|replacement| diagnostic.apply(replacement).ok())
+					.map(|(range, new_text)| TextEdit {
+						range: byte_range_to_range(&source, range),
+						new_text,
+					})
+					.collect::<Vec<_>>();
 
 				Diagnostic {
-					range: Range {
-						start: lsp_types::Position {
-							line: start_line as u32,
-							character: start_column as u32,
-						},
-						end: lsp_types::Position {
-							line: end_line as u32,
-							character: end_column as u32,
-						},
-					},
+					range: *range,
 					severity: Some(DiagnosticSeverity::INFORMATION),
-					code: Some(NumberOrString::String(diagnostic.rule_id)),
+					code: Some(NumberOrString::String(diagnostic.rule_id.clone())),
 					code_description: None,
 					source: None,
-					message: diagnostic.message,
+					message: diagnostic.message.clone(),
 					related_information: None,
 					tags: None,
-					data: serde_json::to_value(diagnostic.replacements).ok(),
+					data: serde_json::to_value(edits).ok(),
 				}
 			})
 			.collect();
+		self.findings.insert(path.to_owned(), findings);
 
 		Ok(diagnostics)
 	}
@@ -579,22 +675,18 @@ where
 	Ok(())
 }
 
-#[derive(Debug)]
-struct Cache {
-	cache: HashMap<String, Vec<Suggestion>>,
-}
-
-impl Cache {
-	pub fn new() -> Self {
-		Self { cache: HashMap::new() }
-	}
-
-	pub fn get(&mut self, text: &str) -> Option<Vec<Suggestion>> {
-		self.cache.remove(text)
-	}
-
-	pub fn insert(&mut self, text: String, suggestions: Vec<Suggestion>) {
-		self.cache.insert(text, suggestions);
+fn byte_range_to_range(source: &Source, range: std::ops::Range<usize>) -> Range {
+	let (start_line, start_column) = byte_to_position(source, range.start);
+	let (end_line, end_column) = byte_to_position(source, range.end);
+	Range {
+		start: Position {
+			line: start_line as u32,
+			character: start_column as u32,
+		},
+		end: Position {
+			line: end_line as u32,
+			character: end_column as u32,
+		},
 	}
 }
 
@@ -605,3 +697,288 @@ fn byte_to_position(source: &Source, index: usize) -> (usize, usize) {
 	let column = head.chars().count();
 	(line, column)
 }
+
+#[cfg(all(test, feature = "remote-server"))]
+mod test {
+	use std::sync::{Arc, Mutex};
+
+	use lt_mock::param;
+
+	use super::*;
+
+	/// A client of a server running on its own thread.
+	struct Client {
+		connection: Connection,
+		server: Option<std::thread::JoinHandle<anyhow::Result<()>>>,
+		next_id: i32,
+		options: Value,
+	}
+
+	impl Client {
+		fn start(options: Value) -> Self {
+			let (server, connection) = Connection::memory();
+			let server = std::thread::spawn(move || {
+				tokio::runtime::Runtime::new()
+					.unwrap()
+					.block_on(serve(server))
+			});
+			let mut client = Self {
+				connection,
+				server: Some(server),
+				next_id: 1,
+				options: options.clone(),
+			};
+			let params = InitializeParams {
+				initialization_options: Some(options),
+				..Default::default()
+			};
+			client.request::<Initialize>(params);
+			client.notify::<Initialized>(InitializedParams {});
+			client
+		}
+
+		fn request<R: lsp_types::request::Request>(&mut self, params: R::Params) -> R::Result {
+			let id = RequestId::from(self.next_id);
+			self.next_id += 1;
+			let request = Request::new(id.clone(), R::METHOD.into(), params);
+			self.connection.sender.send(request.into()).unwrap();
+			loop {
+				match self.connection.receiver.recv().unwrap() {
+					Message::Response(response) if response.id == id => {
+						return serde_json::from_value(response.result.unwrap()).unwrap();
+					},
+					_ => continue,
+				}
+			}
+		}
+
+		fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) {
+			let notification = Notification::new(N::METHOD.into(), params);
+			self.connection.sender.send(notification.into()).unwrap();
+		}
+
+		/// Changes the options the server was started with.
+		fn configure(&mut self, changes: Value) {
+			for (key, value) in changes.as_object().unwrap() {
+				self.options[key] = value.clone();
+			}
+			self.notify::<DidChangeConfiguration>(DidChangeConfigurationParams {
+				settings: self.options.clone(),
+			});
+		}
+
+		fn shutdown(mut self) {
+			self.request::<Shutdown>(());
+			self.notify::<Exit>(());
+			self.server.take().unwrap().join().unwrap().unwrap();
+		}
+	}
+
+	/// Starts a server checking the main file of a temporary folder, opened with the text.
+	fn open(name: &str, text: &str, options: Value) -> (Client, Url, Arc<Mutex<Vec<String>>>) {
+		let root = std::env::temp_dir()
+			.join("typst-languagetool-lsp")
+			.join(name);
+		std::fs::create_dir_all(&root).unwrap();
+		let main = root.canonicalize().unwrap().join("main.typ");
+		std::fs::write(&main, "").unwrap();
+		let (port, bodies) = lt_mock::typo_server();
+
+		let mut options = options;
+		options["host"] = "http://127.0.0.1".into();
+		options["port"] = port.to_string().into();
+		options["main"] = serde_json::to_value(&main).unwrap();
+		let client = Client::start(options);
+		let uri = Url::from_file_path(&main).unwrap();
+		client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+			text_document: TextDocumentItem::new(uri.clone(), "typst".into(), 0, text.into()),
+		});
+		(client, uri, bodies)
+	}
+
+	fn diagnostics(client: &mut Client, uri: &Url) -> Vec<Diagnostic> {
+		let report = client.request::<DocumentDiagnosticRequest>(DocumentDiagnosticParams {
+			text_document: TextDocumentIdentifier::new(uri.clone()),
+			identifier: None,
+			previous_result_id: None,
+			work_done_progress_params: Default::default(),
+			partial_result_params: Default::default(),
+		});
+		let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) = report
+		else {
+			panic!("expected a full report");
+		};
+		report.full_document_diagnostic_report.items
+	}
+
+	fn code_actions(client: &mut Client, uri: &Url, diagnostic: Diagnostic) -> Vec<CodeAction> {
+		let actions = client.request::<CodeActionRequest>(CodeActionParams {
+			text_document: TextDocumentIdentifier::new(uri.clone()),
+			range: diagnostic.range,
+			context: CodeActionContext {
+				diagnostics: vec![diagnostic],
+				only: None,
+				trigger_kind: None,
+			},
+			work_done_progress_params: Default::default(),
+			partial_result_params: Default::default(),
+		});
+		actions
+			.unwrap_or_default()
+			.into_iter()
+			.map(|action| match action {
+				CodeActionOrCommand::CodeAction(action) => action,
+				CodeActionOrCommand::Command(_) => panic!("expected a code action"),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_document_diagnostic() {
+		let (mut client, uri, request) = open(
+			"document-diagnostic",
+			"A tpyo.",
+			serde_json::json!({ "enabled_checks": { "en-GB": ["OXFORD_SPELLING"] } }),
+		);
+		let items = diagnostics(&mut client, &uri);
+		assert_eq!(items.len(), 1);
+		assert_eq!(
+			items[0].range,
+			Range::new(Position::new(0, 2), Position::new(0, 6))
+		);
+		assert_eq!(
+			items[0].code,
+			Some(NumberOrString::String("MORFOLOGIK_RULE_EN_US".into()))
+		);
+		let bodies = request.lock().unwrap();
+		assert_eq!(
+			param(&bodies[0], "enabledRules").as_deref(),
+			Some("OXFORD_SPELLING")
+		);
+		client.shutdown();
+	}
+
+	#[test]
+	fn test_code_action() {
+		let (mut client, uri, _) =
+			open("code-action", "Some\ntext, a tpyo.", serde_json::json!({}));
+		let items = diagnostics(&mut client, &uri);
+		assert_eq!(items.len(), 1);
+		let actions = code_actions(&mut client, &uri, items[0].clone());
+		assert_eq!(actions.len(), 1);
+		assert_eq!(actions[0].title, "typo");
+		let changes = actions[0].edit.clone().unwrap().changes.unwrap();
+		assert_eq!(
+			changes[&uri],
+			vec![TextEdit {
+				range: Range::new(Position::new(1, 8), Position::new(1, 12)),
+				new_text: "typo".into(),
+			}]
+		);
+		client.shutdown();
+	}
+
+	#[test]
+	fn test_code_action_disjoint() {
+		let (mut client, uri, _) = open(
+			"code-action-disjoint",
+			"A #strong[tp]yo.",
+			serde_json::json!({}),
+		);
+		let items = diagnostics(&mut client, &uri);
+		assert_eq!(items.len(), 1);
+		assert!(code_actions(&mut client, &uri, items[0].clone()).is_empty());
+		client.shutdown();
+	}
+
+	#[test]
+	fn test_hover() {
+		let (mut client, uri, _) = open("hover", "Some text, a tpyo.", serde_json::json!({}));
+		diagnostics(&mut client, &uri);
+		let hover = |client: &mut Client, character| {
+			client.request::<HoverRequest>(HoverParams {
+				text_document_position_params: TextDocumentPositionParams {
+					text_document: TextDocumentIdentifier::new(uri.clone()),
+					position: Position::new(0, character),
+				},
+				work_done_progress_params: Default::default(),
+			})
+		};
+		let Some(Hover {
+			contents: HoverContents::Markup(content),
+			range,
+		}) = hover(&mut client, 15)
+		else {
+			panic!("expected a hover");
+		};
+		assert_eq!(
+			range,
+			Some(Range::new(Position::new(0, 13), Position::new(0, 17)))
+		);
+		assert!(content
+			.value
+			.contains("`MORFOLOGIK_RULE_EN_US` (TYPOS): Possible Typo"));
+		assert!(hover(&mut client, 2).is_none());
+		client.shutdown();
+	}
+
+	#[test]
+	fn test_incremental_change() {
+		let (mut client, uri, bodies) = open(
+			"incremental-change",
+			"A big tpyo.\n\nSecond paragraph.",
+			serde_json::json!({ "chunk_size": 1 }),
+		);
+		assert_eq!(diagnostics(&mut client, &uri).len(), 1);
+		assert_eq!(bodies.lock().unwrap().len(), 2);
+
+		client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+			text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 1),
+			content_changes: vec![TextDocumentContentChangeEvent {
+				range: Some(Range::new(Position::new(2, 0), Position::new(2, 6))),
+				range_length: None,
+				text: "Another tpyo in the second".into(),
+			}],
+		});
+		let items = diagnostics(&mut client, &uri);
+		let ranges = items.iter().map(|item| item.range).collect::<Vec<_>>();
+		assert_eq!(
+			ranges,
+			vec![
+				Range::new(Position::new(0, 6), Position::new(0, 10)),
+				Range::new(Position::new(2, 8), Position::new(2, 12)),
+			]
+		);
+		let bodies = bodies.lock().unwrap();
+		let texts = bodies[2..]
+			.iter()
+			.map(|body| param(body, "text").unwrap())
+			.collect::<Vec<_>>();
+		assert_eq!(texts.len(), 1);
+		assert!(texts[0].contains("Another tpyo in the second paragraph."));
+		client.shutdown();
+	}
+
+	#[test]
+	fn test_config_change() {
+		let (mut client, uri, bodies) = open("config-change", "A tpyo.", serde_json::json!({}));
+		assert_eq!(diagnostics(&mut client, &uri).len(), 1);
+		let checked = bodies.lock().unwrap().len();
+		// the unchanged text is taken from the cache
+		diagnostics(&mut client, &uri);
+		assert_eq!(bodies.lock().unwrap().len(), checked);
+
+		// but checked again with changed rules
+		client.configure(serde_json::json!({
+			"disabled_checks": { "en-GB": ["MORFOLOGIK_RULE_EN_US"] },
+		}));
+		diagnostics(&mut client, &uri);
+		let bodies = bodies.lock().unwrap();
+		assert_eq!(bodies.len(), checked + 1);
+		assert_eq!(
+			param(&bodies[checked], "disabledRules").as_deref(),
+			Some("MORFOLOGIK_RULE_EN_US")
+		);
+		client.shutdown();
+	}
+}
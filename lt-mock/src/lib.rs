@@ -0,0 +1,202 @@
+//! A mock LanguageTool server for the tests of the workspace.
+
+use std::{
+	io::{Read, Write},
+	net::TcpListener,
+	ops::Not,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex,
+	},
+	time::Duration,
+};
+
+/// Serves http responses with the status and body returned for the n-th request and its text.
+///
+/// Returns the port the server listens on.
+pub fn serve(handler: impl Fn(usize, &str) -> (u16, String) + Send + Sync + 'static) -> u16 {
+	serve_with_delay(Duration::ZERO, handler).0
+}
+
+/// Like [`serve`], but responds after the delay and counts the most concurrent requests.
+pub fn serve_with_delay(
+	delay: Duration,
+	handler: impl Fn(usize, &str) -> (u16, String) + Send + Sync + 'static,
+) -> (u16, Arc<AtomicUsize>) {
+	let active = Arc::new(AtomicUsize::new(0));
+	let max_active = Arc::new(AtomicUsize::new(0));
+	let max = max_active.clone();
+	let port = listen(move |n, request| {
+		let (status, body) = handler(n, &String::from_utf8_lossy(request));
+		let current = active.fetch_add(1, Ordering::SeqCst) + 1;
+		max_active.fetch_max(current, Ordering::SeqCst);
+		std::thread::sleep(delay);
+		active.fetch_sub(1, Ordering::SeqCst);
+		(status, body)
+	});
+	(port, max)
+}
+
+/// Like [`serve`], but passes the head and the undecoded body of the request.
+pub fn serve_raw(handler: impl Fn(&str, &[u8]) -> (u16, String) + Send + Sync + 'static) -> u16 {
+	listen(move |_, request| {
+		let end = head_end(request).unwrap_or(request.len());
+		let head = String::from_utf8_lossy(&request[..end]);
+		handler(&head, request.get(end + 4..).unwrap_or_default())
+	})
+}
+
+/// Serves check requests, with a typo match for every `tpyo` in the text.
+///
+/// Keeps the bodies of the requests.
+pub fn typo_server() -> (u16, Arc<Mutex<Vec<String>>>) {
+	typo_server_with_version("6.4")
+}
+
+/// Like [`typo_server`], but reports the version of LanguageTool.
+pub fn typo_server_with_version(version: &'static str) -> (u16, Arc<Mutex<Vec<String>>>) {
+	let bodies = Arc::new(Mutex::new(Vec::new()));
+	let captured = bodies.clone();
+	let port = serve(move |_, request| {
+		let body = body(request);
+		captured.lock().unwrap().push(body.to_owned());
+		let text = param(body, "text").unwrap_or_default();
+		let matches = text
+			.match_indices("tpyo")
+			.map(|(index, _)| typo_match(text[..index].encode_utf16().count()))
+			.collect::<Vec<_>>()
+			.join(",");
+		(200, response_with_version(&matches, version))
+	});
+	(port, bodies)
+}
+
+fn listen(handler: impl Fn(usize, &[u8]) -> (u16, String) + Send + Sync + 'static) -> u16 {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let port = listener.local_addr().unwrap().port();
+	let handler = Arc::new(handler);
+	let count = Arc::new(AtomicUsize::new(0));
+	std::thread::spawn(move || {
+		for stream in listener.incoming() {
+			let Ok(mut stream) = stream else {
+				continue;
+			};
+			let handler = handler.clone();
+			let count = count.clone();
+			// concurrent requests are answered concurrently
+			std::thread::spawn(move || {
+				let Some(request) = read_request(&mut stream) else {
+					return;
+				};
+				let (status, body) = handler(count.fetch_add(1, Ordering::SeqCst), &request);
+				let response = format!(
+					"HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\n\
+					 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+					body.len()
+				);
+				let _ = stream.write_all(response.as_bytes());
+			});
+		}
+	});
+	port
+}
+
+/// Reads the head and the whole body of the request, `None` if the connection closes before.
+pub fn read_request(stream: &mut impl Read) -> Option<Vec<u8>> {
+	let mut request = Vec::new();
+	let mut buffer = [0; 4096];
+	while complete(&request).not() {
+		let n = stream.read(&mut buffer).ok()?;
+		if n == 0 {
+			return None;
+		}
+		request.extend_from_slice(&buffer[..n]);
+	}
+	Some(request)
+}
+
+/// Whether the request contains the head and the whole body, counted in bytes.
+pub fn complete(request: &[u8]) -> bool {
+	let Some(end) = head_end(request) else {
+		return false;
+	};
+	let length = String::from_utf8_lossy(&request[..end])
+		.lines()
+		.filter_map(|line| line.split_once(':'))
+		.find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+		.and_then(|(_, value)| value.trim().parse().ok())
+		.unwrap_or(0);
+	request.len() - (end + 4) >= length
+}
+
+fn head_end(request: &[u8]) -> Option<usize> {
+	request.windows(4).position(|w| w == b"\r\n\r\n")
+}
+
+/// The body of the request.
+pub fn body(request: &str) -> &str {
+	request
+		.split_once("\r\n\r\n")
+		.map(|(_, body)| body)
+		.unwrap_or_default()
+}
+
+/// The decoded value of the form parameter.
+pub fn param(body: &str, name: &str) -> Option<String> {
+	body.split('&')
+		.filter_map(|param| param.split_once('='))
+		.find(|(key, _)| *key == name)
+		.map(|(_, value)| decode(value))
+}
+
+/// Decodes a form encoded value.
+pub fn decode(value: &str) -> String {
+	let value = value.replace('+', " ");
+	let mut bytes = Vec::new();
+	let mut rest = value.as_bytes();
+	while let Some((&byte, tail)) = rest.split_first() {
+		match (byte, tail.get(..2)) {
+			(b'%', Some(hex)) => {
+				let hex = std::str::from_utf8(hex).unwrap();
+				bytes.push(u8::from_str_radix(hex, 16).unwrap());
+				rest = &tail[2..];
+			},
+			_ => {
+				bytes.push(byte);
+				rest = tail;
+			},
+		}
+	}
+	String::from_utf8(bytes).unwrap()
+}
+
+/// A match with the message, to be used in [`response`].
+pub fn message_match(message: &str) -> String {
+	located_match(message, 0, 1)
+}
+
+/// A match with the message at the offset and length in UTF-16 code units.
+pub fn located_match(message: &str, offset: usize, length: usize) -> String {
+	format!(
+		r#"{{"context":{{"length":0,"offset":0,"text":""}},"length":{length},"message":"{message}","offset":{offset},"replacements":[],"rule":{{"category":{{"id":"","name":""}},"description":"","id":"","issueType":""}},"sentence":"","shortMessage":""}}"#
+	)
+}
+
+/// A spelling match for `tpyo` at the offset in UTF-16 code units.
+pub fn typo_match(offset: usize) -> String {
+	format!(
+		r#"{{"context":{{"length":0,"offset":0,"text":""}},"length":4,"message":"Possible typo","offset":{offset},"replacements":[{{"value":"typo"}}],"rule":{{"category":{{"id":"TYPOS","name":"Typos"}},"description":"Possible Typo","id":"MORFOLOGIK_RULE_EN_US","issueType":"misspelling"}},"sentence":"","shortMessage":""}}"#
+	)
+}
+
+/// A check response body with the given matches.
+pub fn response(matches: &str) -> String {
+	response_with_version(matches, "6.4")
+}
+
+/// Like [`response`], but reports the version of LanguageTool.
+pub fn response_with_version(matches: &str, version: &str) -> String {
+	format!(
+		r#"{{"language":{{"code":"en-US","detectedLanguage":{{"code":"en-US","name":"English"}},"name":"English"}},"matches":[{matches}],"software":{{"apiVersion":1,"buildDate":"","name":"LanguageTool","premium":false,"status":"","version":"{version}"}}}}"#
+	)
+}
@@ -1,4 +1,7 @@
-use std::{path::PathBuf, sync::OnceLock};
+use std::{
+	path::{Path, PathBuf},
+	sync::OnceLock,
+};
 
 use comemo::Prehashed;
 use fontdb::Database;
@@ -29,11 +32,15 @@ pub struct FontManager {
 }
 
 impl FontManager {
-	pub fn new() -> Self {
+	/// Loads the fonts in the folders, the system fonts and the embedded fonts.
+	pub fn new(font_paths: &[impl AsRef<Path>]) -> Self {
 		let mut book = FontBook::new();
 		let mut fonts = Vec::new();
 
 		let mut db = Database::new();
+		for path in font_paths {
+			db.load_fonts_dir(path);
+		}
 		db.load_system_fonts();
 
 		for face in db.faces() {
@@ -31,6 +31,15 @@ pub struct LtWorld {
 
 impl LtWorld {
 	pub fn new(main: PathBuf, root: Option<PathBuf>) -> Self {
+		Self::with_font_paths(main, root, &[] as &[PathBuf])
+	}
+
+	/// Like [`LtWorld::new`], but also uses the fonts in the folders.
+	pub fn with_font_paths(
+		main: PathBuf,
+		root: Option<PathBuf>,
+		font_paths: &[impl AsRef<Path>],
+	) -> Self {
 		let root = root.unwrap_or_else(|| main.parent().unwrap().to_path_buf());
 		let main = VirtualPath::new(main.strip_prefix(&root).unwrap());
 		let mut inputs = Dict::new();
@@ -39,7 +48,7 @@ impl LtWorld {
 		Self {
 			library: Prehashed::new(Library::builder().with_inputs(inputs).build()),
 			now: chrono::Utc::now(),
-			font_manager: FontManager::new(),
+			font_manager: FontManager::new(font_paths),
 			main: FileId::new(None, main),
 			root,
 			shadow_files: HashMap::new(),
@@ -91,6 +91,10 @@ impl LanguageToolJNI {
 				.call_method(&m, "getMessage", "()Ljava/lang/String;", &[])?
 				.l()?;
 			let message = env.get_string(&message.into())?.into();
+			let short_message = env
+				.call_method(&m, "getShortMessage", "()Ljava/lang/String;", &[])?
+				.l()?;
+			let short_message = env.get_string(&short_message.into())?.into();
 
 			let replacements = env
 				.call_method(&m, "getSuggestedReplacements", "()Ljava/util/List;", &[])?
@@ -117,14 +121,36 @@ impl LanguageToolJNI {
 				.call_method(&rule, "getDescription", "()Ljava/lang/String;", &[])?
 				.l()?;
 			let rule_description = env.get_string(&rule_description.into())?.into();
+			let category = env
+				.call_method(
+					&rule,
+					"getCategory",
+					"()Lorg/languagetool/rules/Category;",
+					&[],
+				)?
+				.l()?;
+			let category = env
+				.call_method(
+					&category,
+					"getId",
+					"()Lorg/languagetool/rules/CategoryId;",
+					&[],
+				)?
+				.l()?;
+			let category = env
+				.call_method(&category, "toString", "()Ljava/lang/String;", &[])?
+				.l()?;
+			let category = env.get_string(&category.into())?.into();
 
 			let suggestion = Suggestion {
 				start: start as usize,
 				end: end as usize,
 				replacements,
 				message,
+				short_message,
 				rule_id,
 				rule_description,
+				category,
 			};
 			suggestions.push(suggestion);
 		}
@@ -203,4 +229,35 @@ impl LanguageToolBackend for LanguageToolJNI {
 		)?;
 		Ok(())
 	}
+
+	async fn enable_checks(&mut self, lang: String, checks: &[String]) -> anyhow::Result<()> {
+		let mut guard = self.jvm.attach_current_thread()?;
+		let lang_tool = match self.languages.entry(lang.clone()) {
+			Entry::Occupied(entry) => entry.into_mut(),
+			Entry::Vacant(entry) => entry.insert(Self::create_lang_tool(lang, &mut guard)?),
+		};
+		for check in checks {
+			let check = guard.new_string(check)?;
+			guard.call_method(
+				&*lang_tool,
+				"enableRule",
+				"(Ljava/lang/String;)V",
+				&[JValue::Object(&check)],
+			)?;
+		}
+		Ok(())
+	}
+
+	async fn version(&mut self) -> anyhow::Result<String> {
+		let mut guard = self.jvm.attach_current_thread()?;
+		let version = guard
+			.get_static_field(
+				"org/languagetool/JLanguageTool",
+				"VERSION",
+				"Ljava/lang/String;",
+			)?
+			.l()?;
+		let version = guard.get_string(&version.into())?.into();
+		Ok(version)
+	}
 }
@@ -1,54 +1,248 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+	collections::{hash_map::RandomState, BTreeMap, BTreeSet, HashMap, HashSet},
+	fmt,
+	hash::{BuildHasher, Hash, Hasher},
+	io::Write,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
 
-use languagetool_rust::{check::Match, CheckRequest, ServerClient};
+use flate2::{write::GzEncoder, Compression};
+use futures_util::{future, stream, Stream, StreamExt, TryStreamExt};
+use languagetool_rust::{
+	check::{self, Match},
+	CheckRequest, CheckResponse, ServerClient,
+};
+use reqwest::header::{HeaderValue, CONTENT_ENCODING};
 
-use crate::{LanguageToolBackend, Suggestion};
+use std::ops::{Not, Range};
+
+use tokio::sync::Semaphore;
+use typst::{model::Document, syntax::Source};
+
+use crate::{
+	convert::{self, ConvertOptions, Fnv, Mapping},
+	line_column, CheckCache, Diagnostic, Error, FileCollector, LanguageToolBackend, Suggestion,
+};
+
+/// Retries of requests failing with server or connection errors.
+#[derive(Debug, Clone)]
+pub struct Retry {
+	/// Attempts of a request including the first one.
+	pub max_attempts: usize,
+	/// Delay before the first retry, doubled for each following one.
+	pub delay: Duration,
+}
+
+impl Default for Retry {
+	fn default() -> Self {
+		Self {
+			max_attempts: 3,
+			delay: Duration::from_millis(500),
+		}
+	}
+}
+
+impl Retry {
+	/// Exponential backoff with up to half of the delay as jitter.
+	fn backoff(&self, attempt: usize) -> Duration {
+		let delay = self.delay * 2u32.saturating_pow(attempt as u32 - 1);
+		let random = RandomState::new().build_hasher().finish();
+		let jitter = delay.mul_f64((random % 1000) as f64 / 2000.0);
+		delay + jitter
+	}
+}
+
+/// Rules and categories of LanguageTool to enable or disable.
+///
+/// Hashable to be part of the key of a [`crate::CheckCache`].
+#[derive(Debug, Clone, Default, Hash)]
+pub struct Rules {
+	pub enabled_rules: Vec<String>,
+	pub disabled_rules: Vec<String>,
+	pub enabled_categories: Vec<String>,
+	pub disabled_categories: Vec<String>,
+	/// Only enable the rules and categories enabled above.
+	pub enabled_only: bool,
+	pub level: Level,
+	/// Language code of the native language of the writer, enables rules for false friends.
+	pub mother_tongue: Option<String>,
+}
+
+/// Level of the rules, picky adds rules for style issues.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Level {
+	#[default]
+	Default,
+	Picky,
+}
+
+/// Account of the premium API, the api key is not shown in debug output.
+#[derive(Clone)]
+pub struct Credentials {
+	pub username: String,
+	pub api_key: String,
+}
+
+impl fmt::Debug for Credentials {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Credentials")
+			.field("username", &self.username)
+			.field("api_key", &"<hidden>")
+			.finish()
+	}
+}
 
 #[derive(Debug)]
 pub struct LanguageToolRemote {
 	server_client: ServerClient,
 	disabled_categories: HashMap<String, Vec<String>>,
+	enabled_checks: HashMap<String, Vec<String>>,
 	allowed_words: HashMap<String, HashSet<String>>,
+	retry: Retry,
+	rules: Rules,
+	credentials: Option<Credentials>,
+	timeout: Duration,
+	gzip: Option<usize>,
+	/// Version of the server from the last response.
+	version: Mutex<Option<String>>,
 }
 
 impl LanguageToolRemote {
+	/// Uses the server at the hostname, which may contain a path prefix, and the port if not empty.
 	pub fn new(hostname: &str, port: &str) -> anyhow::Result<Self> {
-		let server_client = ServerClient::new(hostname, port);
+		let mut url = parse_url(hostname)?;
+		if port.is_empty().not() {
+			let port = port
+				.parse()
+				.map_err(|_| anyhow::anyhow!("invalid port {port:?}"))?;
+			// urls with a host can have a port
+			url.set_port(Some(port)).unwrap();
+		}
+		Self::from_url(url.as_str())
+	}
+
+	/// Uses the server at the base url, like `https://api.languagetoolplus.com` for the premium
+	/// API or `https://tools.internal/lt` behind a proxy, checks are sent to `{url}/v2/check`.
+	pub fn from_url(url: &str) -> anyhow::Result<Self> {
+		let url = parse_url(url)?;
+		let mut server_client = ServerClient::default();
+		server_client.api = format!("{}/v2", url.as_str().trim_end_matches('/'));
 		Ok(Self {
 			server_client,
 			disabled_categories: HashMap::new(),
+			enabled_checks: HashMap::new(),
 			allowed_words: HashMap::new(),
+			retry: Retry::default(),
+			rules: Rules::default(),
+			credentials: None,
+			timeout: Duration::from_secs(30),
+			gzip: None,
+			version: Mutex::new(None),
 		})
 	}
-}
 
-impl LanguageToolBackend for LanguageToolRemote {
-	async fn allow_words(&mut self, lang: String, words: &[String]) -> anyhow::Result<()> {
-		self.allowed_words
-			.insert(lang, words.iter().map(|x| x.clone()).collect());
-		Ok(())
+	pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+		self.credentials = Some(credentials);
+		self
 	}
 
-	async fn disable_checks(&mut self, lang: String, checks: &[String]) -> anyhow::Result<()> {
-		self.disabled_categories
-			.insert(lang, checks.iter().map(|x| x.clone()).collect());
-		Ok(())
+	/// Time to wait for the response of each request, 30 seconds by default.
+	pub fn with_timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = timeout;
+		self
 	}
 
-	async fn check_text(
-		&mut self,
-		lang: String,
-		text: &str,
-	) -> anyhow::Result<Vec<crate::Suggestion>> {
-		let disabled_rules = self.disabled_categories.get(&lang).map(|x| x.clone());
-		let allowed = self.allowed_words.get(&lang);
+	pub fn with_retry(mut self, retry: Retry) -> Self {
+		self.retry = retry;
+		self
+	}
+
+	pub fn with_rules(mut self, rules: Rules) -> Self {
+		self.rules = rules;
+		self
+	}
+
+	/// Compresses request bodies of at least `min_size` bytes with gzip.
+	pub fn with_gzip(mut self, min_size: usize) -> Self {
+		self.gzip = Some(min_size);
+		self
+	}
+
+	fn check_request(&self, lang: String, text: &str) -> CheckRequest {
+		let non_empty = |rules: Vec<String>| Some(rules).filter(|rules| rules.is_empty().not());
+		let disabled_rules = self
+			.disabled_categories
+			.get(&lang)
+			.into_iter()
+			.flatten()
+			.chain(&self.rules.disabled_rules)
+			.cloned()
+			.collect();
+		let enabled_rules = self
+			.enabled_checks
+			.get(&lang)
+			.into_iter()
+			.flatten()
+			.chain(&self.rules.enabled_rules)
+			.cloned()
+			.collect();
 
 		let mut req = CheckRequest::default()
 			.with_text(String::from(text))
 			.with_language(lang);
-		req.disabled_rules = disabled_rules;
+		req.enabled_rules = non_empty(enabled_rules);
+		req.disabled_rules = non_empty(disabled_rules);
+		req.enabled_categories = non_empty(self.rules.enabled_categories.clone());
+		req.disabled_categories = non_empty(self.rules.disabled_categories.clone());
+		req.enabled_only = self.rules.enabled_only;
+		req.level = match self.rules.level {
+			Level::Default => check::Level::Default,
+			Level::Picky => check::Level::Picky,
+		};
+		req.mother_tongue = self.rules.mother_tongue.clone();
+		if let Some(credentials) = &self.credentials {
+			req.username = Some(credentials.username.clone());
+			req.api_key = Some(credentials.api_key.clone());
+		}
+		req
+	}
+
+	/// Hash of the rules and the checks and allowed words of every language, sorted by language.
+	///
+	/// Part of the key of cached results, so other settings do not reuse them.
+	fn settings_hash(&self) -> u64 {
+		let mut hasher = Fnv::default();
+		self.rules.hash(&mut hasher);
+		for checks in [&self.disabled_categories, &self.enabled_checks] {
+			checks.iter().collect::<BTreeMap<_, _>>().hash(&mut hasher);
+		}
+		let allowed_words = self
+			.allowed_words
+			.iter()
+			.map(|(lang, words)| (lang, words.iter().collect::<BTreeSet<_>>()))
+			.collect::<BTreeMap<_, _>>();
+		allowed_words.hash(&mut hasher);
+		hasher.finish()
+	}
+
+	/// Version and build date of the server, asked with a short check if no response arrived yet.
+	///
+	/// Part of the key of cached results, so an upgraded server does not reuse them.
+	pub async fn server_version(&self) -> Result<String, Error> {
+		if let Some(version) = self.version.lock().unwrap().clone() {
+			return Ok(version);
+		}
+		let req = self.check_request("en-US".into(), ".");
+		self.request(&req).await?;
+		// every successful response sets the version
+		Ok(self.version.lock().unwrap().clone().unwrap_or_default())
+	}
 
-		let response = self.server_client.check(&req).await?;
+	async fn suggestions(&self, lang: String, text: &str) -> Result<Vec<Suggestion>, Error> {
+		let allowed = self.allowed_words.get(&lang);
+		let req = self.check_request(lang, text);
+		let response = self.request(&req).await?;
 
 		let mut suggestions = Vec::with_capacity(response.matches.len());
 		for m in response.matches {
@@ -57,19 +251,355 @@ impl LanguageToolBackend for LanguageToolRemote {
 					continue;
 				}
 			}
-			let suggestion = Suggestion {
-				start: m.offset,
-				end: m.offset + m.length,
-				message: m.message,
-				rule_description: m.rule.description,
-				rule_id: m.rule.id,
-				replacements: m.replacements.into_iter().map(|x| x.value).collect(),
-			};
+			let suggestion = Suggestion::from(m);
 			suggestions.push(suggestion);
 		}
 
 		Ok(suggestions)
 	}
+
+	/// Checks the chunks with up to `concurrency` requests at a time.
+	///
+	/// The results are in the order of the chunks.
+	pub async fn check_chunks(
+		&self,
+		chunks: Vec<(String, Mapping)>,
+		concurrency: usize,
+	) -> Result<Vec<(Vec<Suggestion>, Mapping)>, Error> {
+		let requests = chunks.into_iter().map(|(text, mapping)| async move {
+			let suggestions = self.suggestions(mapping.long_language(), &text).await?;
+			Ok((suggestions, mapping))
+		});
+		stream::iter(requests)
+			.buffered(concurrency.max(1))
+			.try_collect()
+			.await
+	}
+
+	/// Checks the chunks like [`Self::check_chunks`], but yields the suggestions of each chunk
+	/// with their locations in the source as soon as its response arrives.
+	///
+	/// The chunks may finish in any order, suggestions for the context of a chunk are skipped.
+	pub fn check_stream<'a>(
+		&'a self,
+		chunks: Vec<(String, Mapping)>,
+		concurrency: usize,
+		source: &'a Source,
+	) -> impl Stream<Item = Result<(Suggestion, Vec<Range<usize>>), Error>> + 'a {
+		let requests = chunks.into_iter().map(move |(text, mapping)| async move {
+			let suggestions = self.suggestions(mapping.long_language(), &text).await?;
+			Ok((suggestions, mapping))
+		});
+		stream::iter(requests)
+			.buffer_unordered(concurrency.max(1))
+			.map_ok(move |(suggestions, mapping)| {
+				let located = suggestions
+					.into_iter()
+					.filter(|suggestion| mapping.is_context(suggestion).not())
+					.filter(|suggestion| mapping.is_inserted(suggestion).not())
+					.map(|suggestion| {
+						let locations = mapping.location(&suggestion, source);
+						Ok((suggestion, locations))
+					})
+					.collect::<Vec<_>>();
+				stream::iter(located)
+			})
+			.try_flatten()
+	}
+
+	fn http_request(&self, req: &CheckRequest) -> Result<reqwest::Request, Error> {
+		let url = format!("{}/check", self.server_client.api);
+		let mut request = self
+			.server_client
+			.client
+			.post(&url)
+			.form(req)
+			.timeout(self.timeout)
+			.build()
+			.map_err(Error::Http)?;
+		let body = request.body().and_then(|body| body.as_bytes());
+		match (self.gzip, body) {
+			(Some(min_size), Some(body)) if body.len() >= min_size => {
+				let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+				// writing to a vector does not fail
+				encoder.write_all(body).unwrap();
+				let body = encoder.finish().unwrap();
+				request
+					.headers_mut()
+					.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+				*request.body_mut() = Some(body.into());
+			},
+			_ => {},
+		}
+		Ok(request)
+	}
+
+	async fn request(&self, req: &CheckRequest) -> Result<CheckResponse, Error> {
+		let request = self.http_request(req)?;
+		let mut attempt = 1;
+		loop {
+			let last = attempt >= self.retry.max_attempts;
+			// bodies of bytes can always be cloned
+			let request = request.try_clone().unwrap();
+			match self.server_client.client.execute(request).await {
+				Ok(response) if response.status().is_success() => {
+					let response: CheckResponse = response.json().await.map_err(|err| {
+						if err.is_decode() {
+							Error::Response(err.to_string())
+						} else {
+							Error::Http(err)
+						}
+					})?;
+					let software = &response.software;
+					let mut version = software.version.clone();
+					if software.build_date.is_empty().not() {
+						version = format!("{version} ({})", software.build_date);
+					}
+					*self.version.lock().unwrap() = Some(version);
+					return Ok(response);
+				},
+				Ok(response) => {
+					let status = response.status();
+					if last || status.is_server_error().not() {
+						let text = response.text().await.unwrap_or_default();
+						return Err(Error::Status { status: status.as_u16(), text });
+					}
+				},
+				Err(err) => {
+					if last || (err.is_connect() || err.is_timeout() || err.is_request()).not() {
+						return Err(if err.is_timeout() {
+							Error::Timeout
+						} else {
+							Error::Http(err)
+						});
+					}
+				},
+			}
+			tokio::time::sleep(self.retry.backoff(attempt)).await;
+			attempt += 1;
+		}
+	}
+}
+
+/// Shared handle of a configured server, its clones together send at most a fixed number of
+/// requests at a time.
+#[derive(Debug, Clone)]
+pub struct CheckClient {
+	remote: Arc<LanguageToolRemote>,
+	permits: Arc<Semaphore>,
+}
+
+impl CheckClient {
+	pub fn new(remote: LanguageToolRemote, max_concurrent: usize) -> Self {
+		Self {
+			remote: Arc::new(remote),
+			permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+		}
+	}
+
+	/// Checks the text, waits while the maximum of requests is in flight.
+	pub async fn check_text(&self, lang: String, text: &str) -> Result<Vec<Suggestion>, Error> {
+		// the semaphore is never closed
+		let _permit = self.permits.acquire().await.unwrap();
+		self.remote.suggestions(lang, text).await
+	}
+
+	/// Checks all chunks at once, limited by the requests of every clone.
+	///
+	/// The results are in the order of the chunks.
+	pub async fn check_chunks(
+		&self,
+		chunks: Vec<(String, Mapping)>,
+	) -> Result<Vec<(Vec<Suggestion>, Mapping)>, Error> {
+		let requests = chunks.into_iter().map(|(text, mapping)| async move {
+			let suggestions = self.check_text(mapping.long_language(), &text).await?;
+			Ok((suggestions, mapping))
+		});
+		future::try_join_all(requests).await
+	}
+}
+
+/// Settings of [`check_document`].
+#[derive(Debug, Clone)]
+pub struct CheckConfig {
+	pub client: CheckClient,
+	pub chunk_size: usize,
+	pub options: ConvertOptions,
+	/// Suggestions of chunks already checked by the client, shared by the clones of the config.
+	pub cache: Arc<Mutex<CheckCache>>,
+}
+
+impl CheckConfig {
+	pub fn new(client: CheckClient) -> Self {
+		Self {
+			client,
+			chunk_size: 1000,
+			options: ConvertOptions::default(),
+			cache: Arc::default(),
+		}
+	}
+}
+
+/// A diagnostic with the zero based line and column of its start.
+#[derive(Debug, Clone)]
+pub struct ResolvedSuggestion {
+	pub diagnostic: Diagnostic,
+	pub line: usize,
+	pub column: usize,
+	/// The source text of every location of the diagnostic, including markup.
+	pub matched: Vec<String>,
+}
+
+/// Converts the text of the file of the source in the document, checks the chunks not in the
+/// cache and locates the suggestions in the source.
+pub async fn check_document(
+	doc: &Document,
+	source: &Source,
+	config: &CheckConfig,
+) -> Result<Vec<ResolvedSuggestion>, Error> {
+	let chunks = convert::document(doc, config.chunk_size, source, &config.options)?;
+	let rules = (
+		config.client.remote.settings_hash(),
+		config.client.remote.server_version().await?,
+	);
+	let mut cached = Vec::with_capacity(chunks.len());
+	let mut unchecked = Vec::new();
+	{
+		let cache = config.cache.lock().unwrap();
+		for (index, (text, mapping)) in chunks.iter().enumerate() {
+			match cache.get(&mapping.long_language(), text, &rules) {
+				Some(suggestions) => cached.push(Some(suggestions.to_vec())),
+				None => {
+					cached.push(None);
+					unchecked.push((index, text.clone(), mapping.clone()));
+				},
+			}
+		}
+	}
+	let (indices, unchecked): (Vec<_>, Vec<_>) = unchecked
+		.into_iter()
+		.map(|(index, text, mapping)| (index, (text, mapping)))
+		.unzip();
+	let checked = config.client.check_chunks(unchecked).await?;
+	let mut cache = config.cache.lock().unwrap();
+	for (index, (suggestions, mapping)) in indices.into_iter().zip(checked) {
+		let text = &chunks[index].0;
+		cache.insert(&mapping.long_language(), text, &rules, suggestions.clone());
+		cached[index] = Some(suggestions);
+	}
+	drop(cache);
+
+	let mut collector = FileCollector::from_source(source.clone());
+	for ((_, mapping), suggestions) in chunks.into_iter().zip(cached) {
+		collector.add(&suggestions.unwrap_or_default(), mapping);
+	}
+	let (source, diagnostics) = collector.finish();
+	let resolved = diagnostics
+		.into_iter()
+		.map(|diagnostic| {
+			let ((line, column), _) =
+				line_column(&source, diagnostic.locations[0].clone()).unwrap_or_default();
+			let matched = diagnostic
+				.locations
+				.iter()
+				.filter_map(|range| source.text().get(range.clone()))
+				.map(str::to_owned)
+				.collect();
+			ResolvedSuggestion { diagnostic, line, column, matched }
+		})
+		.collect();
+	Ok(resolved)
+}
+
+/// Blocking handle of a [`CheckClient`] for synchronous code, the requests run on a runtime of
+/// its own.
+///
+/// Blocks the thread, so it must not be used within an async runtime.
+#[derive(Debug)]
+pub struct BlockingClient {
+	client: CheckClient,
+	runtime: tokio::runtime::Runtime,
+}
+
+impl BlockingClient {
+	pub fn new(client: CheckClient) -> anyhow::Result<Self> {
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.enable_all()
+			.build()?;
+		Ok(Self { client, runtime })
+	}
+
+	/// The shared async client, its clones can be used in async code at the same time.
+	pub fn client(&self) -> &CheckClient {
+		&self.client
+	}
+
+	pub fn check_text(&self, lang: String, text: &str) -> Result<Vec<Suggestion>, Error> {
+		self.runtime.block_on(self.client.check_text(lang, text))
+	}
+
+	/// See [`CheckClient::check_chunks`].
+	pub fn check_chunks(
+		&self,
+		chunks: Vec<(String, Mapping)>,
+	) -> Result<Vec<(Vec<Suggestion>, Mapping)>, Error> {
+		self.runtime.block_on(self.client.check_chunks(chunks))
+	}
+}
+
+impl LanguageToolBackend for LanguageToolRemote {
+	async fn allow_words(&mut self, lang: String, words: &[String]) -> anyhow::Result<()> {
+		self.allowed_words
+			.insert(lang, words.iter().cloned().collect());
+		Ok(())
+	}
+
+	async fn disable_checks(&mut self, lang: String, checks: &[String]) -> anyhow::Result<()> {
+		self.disabled_categories.insert(lang, checks.to_vec());
+		Ok(())
+	}
+
+	async fn enable_checks(&mut self, lang: String, checks: &[String]) -> anyhow::Result<()> {
+		self.enabled_checks.insert(lang, checks.to_vec());
+		Ok(())
+	}
+
+	async fn check_text(
+		&mut self,
+		lang: String,
+		text: &str,
+	) -> anyhow::Result<Vec<crate::Suggestion>> {
+		Ok(self.suggestions(lang, text).await?)
+	}
+
+	async fn version(&mut self) -> anyhow::Result<String> {
+		Ok(self.server_version().await?)
+	}
+}
+
+/// Parses the url of a server, which must have a host and use http or https.
+fn parse_url(url: &str) -> anyhow::Result<reqwest::Url> {
+	let url =
+		reqwest::Url::parse(url).map_err(|err| anyhow::anyhow!("invalid url {url:?}: {err}"))?;
+	if matches!(url.scheme(), "http" | "https").not() || url.host().is_none() {
+		anyhow::bail!("invalid url {url:?}: expected an http or https url with a host");
+	}
+	Ok(url)
+}
+
+impl From<Match> for Suggestion {
+	fn from(m: Match) -> Self {
+		Self {
+			start: m.offset,
+			end: m.offset + m.length,
+			message: m.message,
+			short_message: m.short_message,
+			rule_description: m.rule.description,
+			rule_id: m.rule.id,
+			category: m.rule.category.id,
+			replacements: m.replacements.into_iter().map(|x| x.value).collect(),
+		}
+	}
 }
 
 fn filter_match(m: &Match, allowed: &HashSet<String>) -> bool {
@@ -86,3 +616,457 @@ fn filter_match(m: &Match, allowed: &HashSet<String>) -> bool {
 	let text = &m.context.text[start..end];
 	allowed.contains(text)
 }
+
+#[cfg(test)]
+mod test {
+	use std::sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex,
+	};
+
+	use lt_mock::{located_match, message_match, response};
+	use typst::text::Lang;
+
+	use super::*;
+	use crate::convert::test::compile;
+
+	/// Serves http responses with the status and body returned for the n-th request and its text.
+	fn mock_server(
+		handler: impl Fn(usize, &str) -> (u16, String) + Send + Sync + 'static,
+	) -> LanguageToolRemote {
+		client(lt_mock::serve(handler))
+	}
+
+	/// Like [`mock_server`], but responds after the delay and counts the most concurrent requests.
+	fn mock_server_with_delay(
+		delay: Duration,
+		handler: impl Fn(usize, &str) -> (u16, String) + Send + Sync + 'static,
+	) -> (LanguageToolRemote, Arc<AtomicUsize>) {
+		let (port, max_active) = lt_mock::serve_with_delay(delay, handler);
+		(client(port), max_active)
+	}
+
+	/// Like [`mock_server`], but responds without matches and keeps the body of the last request.
+	fn capturing_server() -> (LanguageToolRemote, Arc<Mutex<String>>) {
+		let body = Arc::new(Mutex::new(String::new()));
+		let captured = body.clone();
+		let lt = mock_server(move |_, request| {
+			*captured.lock().unwrap() = lt_mock::body(request).into();
+			(200, response(""))
+		});
+		(lt, body)
+	}
+
+	/// The head and the undecoded body of a request.
+	type RawRequest = Arc<Mutex<(String, Vec<u8>)>>;
+
+	/// Serves empty check responses, keeps the head and the undecoded body of the last request.
+	fn raw_server() -> (LanguageToolRemote, RawRequest) {
+		let last = Arc::new(Mutex::new((String::new(), Vec::new())));
+		let captured = last.clone();
+		let port = lt_mock::serve_raw(move |head, body| {
+			*captured.lock().unwrap() = (head.to_lowercase(), body.to_vec());
+			(200, response(""))
+		});
+		(client(port), last)
+	}
+
+	fn client(port: u16) -> LanguageToolRemote {
+		LanguageToolRemote::new("http://127.0.0.1", &port.to_string()).unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_retry() {
+		let lt = mock_server(|n, _| match n {
+			0 | 1 => (502, "Bad Gateway".into()),
+			_ => (200, response("")),
+		});
+		let retry = Retry {
+			max_attempts: 3,
+			delay: Duration::from_millis(1),
+		};
+		let mut lt = lt.with_retry(retry.clone());
+		let suggestions = lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		assert!(suggestions.is_empty());
+
+		let requests = Arc::new(AtomicUsize::new(0));
+		let counter = requests.clone();
+		let lt = mock_server(move |_, _| {
+			counter.fetch_add(1, Ordering::SeqCst);
+			(400, "Bad Request".into())
+		});
+		let mut lt = lt.with_retry(retry);
+		assert!(lt.check_text("en-US".into(), "Some text.").await.is_err());
+		assert_eq!(requests.load(Ordering::SeqCst), 1);
+	}
+
+	#[tokio::test]
+	async fn test_server_version() {
+		let requests = Arc::new(AtomicUsize::new(0));
+		let counter = requests.clone();
+		let mut lt = mock_server(move |n, _| {
+			counter.fetch_add(1, Ordering::SeqCst);
+			let body = response("");
+			match n {
+				0 => (200, body),
+				_ => (
+					200,
+					body.replace("\"buildDate\":\"\"", "\"buildDate\":\"2024-06-28\""),
+				),
+			}
+		});
+		assert_eq!(lt.server_version().await.unwrap(), "6.4");
+		assert_eq!(lt.version().await.unwrap(), "6.4");
+		assert_eq!(requests.load(Ordering::SeqCst), 1);
+		// every response updates the version
+		lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		assert_eq!(lt.server_version().await.unwrap(), "6.4 (2024-06-28)");
+		assert_eq!(requests.load(Ordering::SeqCst), 2);
+	}
+
+	#[tokio::test]
+	async fn test_suggestion() {
+		let lt = mock_server(|_, _| {
+			let m = r#"{"context":{"length":4,"offset":9,"text":"Here is ä tpyo."},"length":4,"message":"Possible spelling mistake found.","offset":10,"replacements":[{"value":"typo"},{"value":"typos"}],"rule":{"category":{"id":"TYPOS","name":"Possible Typo"},"description":"Possible spelling mistake","id":"MORFOLOGIK_RULE_EN_US","issueType":"misspelling"},"sentence":"Here is ä tpyo.","shortMessage":"Spelling mistake"}"#;
+			(200, response(m))
+		});
+		let text = "Here is ä tpyo.";
+		let suggestions = lt.suggestions("en-US".into(), text).await.unwrap();
+		assert_eq!(suggestions.len(), 1);
+		let suggestion = &suggestions[0];
+		assert_eq!((suggestion.start, suggestion.end), (10, 14));
+		assert_eq!(&text[suggestion.utf_8_range(text)], "tpyo");
+		assert_eq!(suggestion.replacements, ["typo", "typos"]);
+		assert_eq!(suggestion.message, "Possible spelling mistake found.");
+		assert_eq!(suggestion.short_message, "Spelling mistake");
+		assert_eq!(suggestion.rule_id, "MORFOLOGIK_RULE_EN_US");
+		assert_eq!(suggestion.rule_description, "Possible spelling mistake");
+		assert_eq!(suggestion.category, "TYPOS");
+	}
+
+	#[tokio::test]
+	async fn test_rules() {
+		let (lt, body) = capturing_server();
+		let mut lt = lt.with_rules(Rules {
+			enabled_rules: vec!["OXFORD_SPELLING".into()],
+			disabled_rules: vec!["COMMA_RULE".into(), "WHITESPACE_RULE".into()],
+			enabled_categories: vec!["STYLE".into()],
+			disabled_categories: vec!["TYPOS".into()],
+			enabled_only: true,
+			..Default::default()
+		});
+		lt.disable_checks("en-US".into(), &["EN_QUOTES".into()])
+			.await
+			.unwrap();
+		lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		let body = body.lock().unwrap().clone();
+		let params = body.split('&').collect::<Vec<_>>();
+		for param in [
+			"text=Some+text.",
+			"language=en-US",
+			"enabledRules=OXFORD_SPELLING",
+			"disabledRules=EN_QUOTES%2CCOMMA_RULE%2CWHITESPACE_RULE",
+			"enabledCategories=STYLE",
+			"disabledCategories=TYPOS",
+			"enabledOnly=true",
+		] {
+			assert!(params.contains(&param), "{param} not in {body}");
+		}
+	}
+
+	#[tokio::test]
+	async fn test_level() {
+		let (lt, body) = capturing_server();
+		let mut lt = lt.with_rules(Rules::default());
+		lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		assert!(body.lock().unwrap().contains("level=").not());
+
+		let mut lt = lt.with_rules(Rules {
+			level: Level::Picky,
+			..Default::default()
+		});
+		lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		let params = body.lock().unwrap().clone();
+		assert!(params.split('&').any(|param| param == "level=picky"));
+	}
+
+	#[tokio::test]
+	async fn test_mother_tongue() {
+		let (mut lt, body) = capturing_server();
+		lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		assert!(body.lock().unwrap().contains("motherTongue").not());
+
+		let mut lt = lt.with_rules(Rules {
+			mother_tongue: Some("de-DE".into()),
+			..Default::default()
+		});
+		lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		let params = body.lock().unwrap().clone();
+		assert!(params.split('&').any(|param| param == "motherTongue=de-DE"));
+	}
+
+	#[tokio::test]
+	async fn test_url() {
+		let target = Arc::new(Mutex::new(String::new()));
+		let captured = target.clone();
+		let lt = mock_server(move |_, request| {
+			*captured.lock().unwrap() = request.lines().next().unwrap().into();
+			(200, response(""))
+		});
+		let base = lt.server_client.api.trim_end_matches("/v2").to_owned();
+		let (host, port) = base.rsplit_once(':').unwrap();
+
+		let mut lt = LanguageToolRemote::from_url(&format!("{base}/lt/")).unwrap();
+		lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		assert_eq!(*target.lock().unwrap(), "POST /lt/v2/check HTTP/1.1");
+
+		let mut lt = LanguageToolRemote::new(&format!("{host}/other/lt"), port).unwrap();
+		lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		assert_eq!(*target.lock().unwrap(), "POST /other/lt/v2/check HTTP/1.1");
+
+		for url in ["localhost:8081", "ftp://localhost", "http://", "no url"] {
+			assert!(LanguageToolRemote::from_url(url).is_err(), "{url}");
+		}
+		assert!(LanguageToolRemote::new("http://localhost", "port").is_err());
+	}
+
+	#[tokio::test]
+	async fn test_gzip() {
+		let (lt, last) = raw_server();
+		let mut lt = lt.with_gzip(100);
+
+		lt.check_text("en-US".into(), "Short text.").await.unwrap();
+		let (head, body) = last.lock().unwrap().clone();
+		assert!(head.contains("content-encoding").not());
+		assert!(String::from_utf8(body)
+			.unwrap()
+			.contains("text=Short+text."));
+
+		let text = "Some longer text.".repeat(10);
+		lt.check_text("en-US".into(), &text).await.unwrap();
+		let (head, body) = last.lock().unwrap().clone();
+		assert!(head.contains("content-encoding: gzip"));
+		assert!(head.contains("content-type: application/x-www-form-urlencoded"));
+		let mut decoded = String::new();
+		let mut decoder = flate2::read::GzDecoder::new(body.as_slice());
+		std::io::Read::read_to_string(&mut decoder, &mut decoded).unwrap();
+		let params = decoded.split('&').collect::<Vec<_>>();
+		assert!(params.contains(&format!("text={}", text.replace(' ', "+")).as_str()));
+		assert!(params.contains(&"language=en-US"));
+	}
+
+	#[tokio::test]
+	async fn test_credentials() {
+		let (mut lt, body) = capturing_server();
+		lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		let params = body.lock().unwrap().clone();
+		assert!(params.contains("username").not());
+		assert!(params.contains("apiKey").not());
+
+		let mut lt = lt.with_credentials(Credentials {
+			username: "user@example.com".into(),
+			api_key: "secret".into(),
+		});
+		lt.check_text("en-US".into(), "Some text.").await.unwrap();
+		let params = body.lock().unwrap().clone();
+		let params = params.split('&').collect::<Vec<_>>();
+		assert!(params.contains(&"username=user%40example.com"));
+		assert!(params.contains(&"apiKey=secret"));
+		assert!(format!("{lt:?}").contains("secret").not());
+	}
+
+	#[tokio::test]
+	async fn test_timeout() {
+		let (lt, _) =
+			mock_server_with_delay(Duration::from_millis(500), |_, _| (200, response("")));
+		let mut lt = lt
+			.with_timeout(Duration::from_millis(50))
+			.with_retry(Retry { max_attempts: 1, ..Default::default() });
+		let err = lt
+			.check_text("en-US".into(), "Some text.")
+			.await
+			.unwrap_err();
+		assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Timeout)));
+	}
+
+	#[tokio::test]
+	async fn test_malformed_response() {
+		let mut lt = mock_server(|_, _| (200, "{\"matches\": [{\"offset\": \"zero\"}]}".into()));
+		let err = lt
+			.check_text("en-US".into(), "Some text.")
+			.await
+			.unwrap_err();
+		assert!(matches!(
+			err.downcast_ref::<Error>(),
+			Some(Error::Response(_))
+		));
+
+		let mut lt = mock_server(|_, _| (404, "Not Found".into()));
+		let err = lt
+			.check_text("en-US".into(), "Some text.")
+			.await
+			.unwrap_err();
+		assert!(matches!(
+			err.downcast_ref::<Error>(),
+			Some(Error::Status { status: 404, .. })
+		));
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn test_check_chunks() {
+		let (lt, max_active) = mock_server_with_delay(Duration::from_millis(50), |_, request| {
+			// the text is sent as a form parameter
+			let text = request.split("text=Chunk+").nth(1).unwrap();
+			let number = text.split(['&', ' ']).next().unwrap();
+			(200, response(&message_match(number)))
+		});
+		let chunks = (0..8)
+			.map(|i| (format!("Chunk {i}"), Mapping::new(Lang::ENGLISH)))
+			.collect();
+		let results = lt.check_chunks(chunks, 3).await.unwrap();
+		let messages = results
+			.iter()
+			.map(|(suggestions, _)| suggestions[0].message.clone())
+			.collect::<Vec<_>>();
+		assert_eq!(messages, ["0", "1", "2", "3", "4", "5", "6", "7"]);
+		assert!(max_active.load(Ordering::SeqCst) <= 3);
+		assert!(max_active.load(Ordering::SeqCst) > 1);
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn test_check_client() {
+		let (lt, max_active) =
+			mock_server_with_delay(Duration::from_millis(50), |_, _| (200, response("")));
+		let client = CheckClient::new(lt, 2);
+		let documents = (0..3).map(|document| {
+			let client = client.clone();
+			tokio::spawn(async move {
+				let chunks = (0..3)
+					.map(|i| {
+						(
+							format!("Chunk {i} of {document}"),
+							Mapping::new(Lang::ENGLISH),
+						)
+					})
+					.collect();
+				client.check_chunks(chunks).await.unwrap().len()
+			})
+		});
+		for document in documents.collect::<Vec<_>>() {
+			assert_eq!(document.await.unwrap(), 3);
+		}
+		assert_eq!(max_active.load(Ordering::SeqCst), 2);
+	}
+
+	#[test]
+	fn test_blocking_client() {
+		let lt = mock_server(|_, request| match request.contains("tpyo") {
+			true => (200, response(&located_match("Possible typo", 0, 4))),
+			false => (200, response("")),
+		});
+		let blocking = BlockingClient::new(CheckClient::new(lt, 2)).unwrap();
+		let suggestions = blocking.check_text("en-US".into(), "tpyo here").unwrap();
+		assert_eq!(suggestions[0].message, "Possible typo");
+		let chunks = ["Some text.", "tpyo again"]
+			.map(|text| (text.to_owned(), Mapping::new(Lang::ENGLISH)))
+			.into();
+		let checked = blocking.check_chunks(chunks).unwrap();
+		assert_eq!(checked[0].0.len(), 0);
+		assert_eq!(checked[1].0.len(), 1);
+
+		fn shared<T: Send + Sync + Clone + 'static>(_: &T) {}
+		shared(blocking.client());
+	}
+
+	#[tokio::test]
+	async fn test_check_document() {
+		let requests = Arc::new(AtomicUsize::new(0));
+		let counter = requests.clone();
+		let lt = mock_server(move |_, request| {
+			counter.fetch_add(1, Ordering::SeqCst);
+			match request.contains("tpyo") {
+				// after the paragraph break and "A "
+				true => (200, response(&located_match("Possible typo", 4, 4))),
+				false => (200, response("")),
+			}
+		});
+		let (doc, source) = compile("A tpyo here.");
+		let config = CheckConfig::new(CheckClient::new(lt, 2));
+		let resolved = check_document(&doc, &source, &config).await.unwrap();
+		assert_eq!(resolved.len(), 1);
+		let typo = &resolved[0];
+		assert_eq!(&source.text()[typo.diagnostic.locations[0].clone()], "tpyo");
+		assert_eq!(typo.diagnostic.message, "Possible typo");
+		assert_eq!((typo.line, typo.column), (0, 2));
+		assert_eq!(typo.matched, ["tpyo"]);
+		let checked = requests.load(Ordering::SeqCst);
+
+		// unchanged chunks are taken from the cache
+		let resolved = check_document(&doc, &source, &config).await.unwrap();
+		assert_eq!(resolved.len(), 1);
+		assert_eq!(requests.load(Ordering::SeqCst), checked);
+
+		// but checked again by an upgraded server
+		let requests = Arc::new(AtomicUsize::new(0));
+		let counter = requests.clone();
+		let lt = mock_server(move |_, _| {
+			counter.fetch_add(1, Ordering::SeqCst);
+			(200, lt_mock::response_with_version("", "6.5"))
+		});
+		let upgraded = CheckConfig {
+			client: CheckClient::new(lt, 2),
+			..config.clone()
+		};
+		let resolved = check_document(&doc, &source, &upgraded).await.unwrap();
+		assert!(resolved.is_empty());
+		// the version and the chunk
+		assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+		// and with other allowed words
+		let requests = Arc::new(AtomicUsize::new(0));
+		let counter = requests.clone();
+		let mut lt = mock_server(move |_, _| {
+			counter.fetch_add(1, Ordering::SeqCst);
+			(200, lt_mock::response_with_version("", "6.5"))
+		});
+		lt.allow_words("en-US".into(), &["tpyo".into()])
+			.await
+			.unwrap();
+		let allowed = CheckConfig {
+			client: CheckClient::new(lt, 2),
+			..config.clone()
+		};
+		check_document(&doc, &source, &allowed).await.unwrap();
+		assert_eq!(requests.load(Ordering::SeqCst), 2);
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn test_check_stream() {
+		let (doc, source) = compile("Alpha one.\n\nBeta two.\n\nGamma three.");
+		let chunks = crate::convert::document(&doc, 1, &source, &Default::default()).unwrap();
+		assert_eq!(chunks.len(), 3);
+
+		let lt = mock_server(|_, request| {
+			// flag the first word after leading newlines
+			let text = request.split("text=").nth(1).unwrap();
+			let trimmed = text.trim_start_matches("%0A");
+			let offset = (text.len() - trimmed.len()) / 3;
+			let word = trimmed.split('+').next().unwrap();
+			(200, response(&located_match(word, offset, word.len())))
+		});
+		let mut results = lt
+			.check_stream(chunks, 2, &source)
+			.try_collect::<Vec<_>>()
+			.await
+			.unwrap();
+		results.sort_by_key(|(_, locations)| locations[0].start);
+		let words = results
+			.iter()
+			.map(|(suggestion, locations)| {
+				assert_eq!(suggestion.message, source.text()[locations[0].clone()]);
+				suggestion.message.as_str()
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(words, ["Alpha", "Beta", "Gamma"]);
+	}
+}
@@ -1,61 +1,328 @@
-use std::ops::Range;
+use std::{
+	cmp::Ordering,
+	collections::{HashMap, HashSet},
+	hash::Hasher,
+	ops::{Not, Range},
+	sync::Arc,
+};
 
 use typst::{
-	layout::{Abs, Em, Point},
-	model::Document,
-	syntax::{FileId, Source, Span, SyntaxKind},
-	text::{Lang, TextItem},
+	foundations::{Packed, StyleChain},
+	layout::{Abs, Dir, Em, Frame, FrameItem, Page, Point, Size},
+	math::EquationElem,
+	model::{Destination, Document},
+	syntax::{
+		ast::{self, AstNode},
+		FileId, LinkedNode, Source, Span, SyntaxKind,
+	},
+	text::{Lang, Region, TextItem},
 };
 
-use crate::Suggestion;
+use crate::{utf_8_index, Error, Suggestion};
+
+mod chars;
+mod join;
+mod markup;
 
-#[derive(Debug)]
+use chars::Chars;
+pub use join::{Context, DefaultJoin, Join, JoinPolicy};
+pub use markup::markup;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Mapping {
-	chars: Vec<(Span, Range<u16>)>,
+	chars: Chars,
 	language: Lang,
+	region: Option<Region>,
+	context: usize,
+	info: ChunkInfo,
+	bibliography: bool,
+	hash: Option<u64>,
+}
+
+/// How completely the chars of a suggestion were found in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+	/// Every char has a location.
+	Complete,
+	/// Some chars, like inserted spaces, have no location and are left out.
+	Partial,
+	/// No char has a location, the location is an empty range next to the closest char having one.
+	Nearest,
+}
+
+/// Where the boundaries of a chunk fall relative to the sentences of the text.
+///
+/// LanguageTool checks sentences split between chunks less accurately,
+/// a larger chunk size or an overlap can avoid this.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChunkInfo {
+	/// The chunk continues a sentence of the previous chunk.
+	pub starts_mid_sentence: bool,
+	/// The chunk ends without terminal punctuation and not at a paragraph break.
+	pub ends_mid_sentence: bool,
 }
 
 impl Mapping {
+	pub(crate) fn new(language: Lang) -> Self {
+		Self {
+			chars: Chars::default(),
+			language,
+			region: None,
+			context: 0,
+			info: ChunkInfo::default(),
+			bibliography: false,
+			hash: None,
+		}
+	}
+
+	/// The mapping of a chunk cut from this one, with the chars of the chunk.
+	fn chunk(&self, chars: Chars, info: ChunkInfo) -> Self {
+		Self { chars, info, hash: None, ..*self }
+	}
+
+	/// Where the chunk starts and ends relative to the sentences of the text.
+	pub fn info(&self) -> ChunkInfo {
+		self.info
+	}
+
+	/// Hash of the text and language of the chunk, only computed with
+	/// [`ConvertOptions::hash_chunks`].
+	///
+	/// Stable across runs and builds, so it can be stored to only check the chunks changed since.
+	pub fn hash(&self) -> Option<u64> {
+		self.hash
+	}
+
+	/// Whether the chunk is text of the bibliography, which is often in several languages.
+	///
+	/// Only returned with [`ConvertOptions::bibliography`], all suggestions are located at the
+	/// `#bibliography(..)` call.
+	pub fn is_bibliography(&self) -> bool {
+		self.bibliography
+	}
+
+	/// Checks if the suggestion is within the text repeated from the previous chunk.
+	pub fn is_context(&self, suggestion: &Suggestion) -> bool {
+		suggestion.end <= self.context
+	}
+
+	/// Checks if the suggestion only covers text inserted by the conversion, like the spaces and
+	/// paragraph breaks between lines, which can not be fixed in the source.
+	pub fn is_inserted(&self, suggestion: &Suggestion) -> bool {
+		suggestion.start < suggestion.end
+			&& self
+				.chars
+				.range(suggestion.start..suggestion.end)
+				.all(|(span, _)| span.is_detached())
+	}
+
+	/// The text of the chunk after the text repeated from the previous chunk.
+	pub fn checked_text<'a>(&self, text: &'a str) -> &'a str {
+		&text[utf_8_index(text, self.context)..]
+	}
+
+	/// Byte ranges of the suggestion in the source.
+	///
+	/// Chars without a location, like inserted spaces, are left out. Without any located char the
+	/// location falls back to the closest char, see [`Mapping::location_resolution`].
 	pub fn location(&self, suggestion: &Suggestion, source: &Source) -> Vec<Range<usize>> {
-		let chars = &self.chars[suggestion.start..suggestion.end];
-		let mut locations = Vec::<Range<usize>>::new();
-		for (span, range) in chars.iter().cloned() {
-			let Some(id) = span.id() else {
-				continue;
-			};
-			if id != source.id() {
-				continue;
-			}
-			let Some(node) = source.find(span) else {
+		self.location_files(suggestion, |id| (id == source.id()).then(|| source.clone()))
+			.into_iter()
+			.map(|(_, range)| range)
+			.collect()
+	}
+
+	/// Like [`Mapping::location`], but for all files the sources are resolved for, like included ones.
+	pub fn location_files(
+		&self,
+		suggestion: &Suggestion,
+		resolve: impl FnMut(FileId) -> Option<Source>,
+	) -> Vec<(FileId, Range<usize>)> {
+		self.resolve(suggestion, resolve).0
+	}
+
+	/// Like [`Mapping::location`], but also tells whether all chars of the suggestion were found.
+	pub fn location_resolution(
+		&self,
+		suggestion: &Suggestion,
+		source: &Source,
+	) -> (Vec<Range<usize>>, Resolution) {
+		let (locations, resolution) =
+			self.resolve(suggestion, |id| (id == source.id()).then(|| source.clone()));
+		let locations = locations.into_iter().map(|(_, range)| range).collect();
+		(locations, resolution)
+	}
+
+	fn resolve(
+		&self,
+		suggestion: &Suggestion,
+		mut resolve: impl FnMut(FileId) -> Option<Source>,
+	) -> (Vec<(FileId, Range<usize>)>, Resolution) {
+		let mut sources = Sources::new(&mut resolve);
+		let mut locations = Vec::<(FileId, Range<usize>)>::new();
+		let mut resolution = Resolution::Complete;
+		for (span, range) in self.chars.range(suggestion.start..suggestion.end) {
+			let Some((id, node_kind, range)) = sources.locate(span, range) else {
+				resolution = Resolution::Partial;
 				continue;
 			};
-			if node.kind() == SyntaxKind::Text {
-				let start = node.range().start;
-				let range = (start + range.start as usize)..(start + range.end as usize);
-				match locations.last_mut() {
-					Some(last_range) if last_range.end == range.start => last_range.end = range.end,
-					_ => locations.push(range),
+			let last = locations.last_mut().filter(|(last_id, _)| *last_id == id);
+			if node_kind == SyntaxKind::Text {
+				match last {
+					Some((_, last_range)) if last_range.end == range.start => {
+						last_range.end = range.end
+					},
+					_ => locations.push((id, range)),
 				}
 			} else {
-				let range = node.range();
-				match locations.last_mut() {
-					Some(last_range) if *last_range == range => {},
-					_ => locations.push(range),
+				match last {
+					Some((_, last_range)) if *last_range == range => {},
+					_ => locations.push((id, range)),
 				}
 			}
 		}
+		// chars of files without a source have a location, just not in the resolved sources
+		if locations.is_empty() && suggestion.start < suggestion.end && sources.missing.not() {
+			if let Some(location) = self.nearest(suggestion, &mut sources) {
+				return (vec![location], Resolution::Nearest);
+			}
+		}
+		(locations, resolution)
+	}
+
+	/// Empty range next to the closest char around the suggestion with a location.
+	fn nearest(
+		&self,
+		suggestion: &Suggestion,
+		sources: &mut Sources<impl FnMut(FileId) -> Option<Source>>,
+	) -> Option<(FileId, Range<usize>)> {
+		let mut locate = |index: usize| {
+			let (span, range) = self.chars.range(index..index + 1).next()?;
+			sources.locate(span, range)
+		};
+		let len = self.chars.len();
+		for distance in 1..=len {
+			let before = suggestion.start.checked_sub(distance);
+			if let Some((id, _, range)) = before.and_then(&mut locate) {
+				return Some((id, range.end..range.end));
+			}
+			let after = suggestion.end + distance - 1;
+			if let Some((id, _, range)) = Some(after)
+				.filter(|&after| after < len)
+				.and_then(&mut locate)
+			{
+				return Some((id, range.start..range.start));
+			}
+			if before.is_none() && after >= len {
+				break;
+			}
+		}
+		None
+	}
+
+	/// Like [`Mapping::location`], but with one range per char of the suggestion.
+	pub fn location_unmerged(&self, suggestion: &Suggestion, source: &Source) -> Vec<Range<usize>> {
+		self.chars
+			.range(suggestion.start..suggestion.end)
+			.filter_map(|(span, range)| Self::source_range(span, range, source))
+			.map(|(_, range)| range)
+			.collect()
+	}
+
+	/// The source lines containing the suggestion and the byte range of it within them.
+	pub fn context<'a>(
+		&self,
+		suggestion: &Suggestion,
+		source: &'a Source,
+	) -> Option<(&'a str, Range<usize>)> {
+		let locations = self.location(suggestion, source);
+		let start = locations.first()?.start;
+		let end = locations.last()?.end;
+		let text = source.text();
+		let line_start = text[..start].rfind('\n').map_or(0, |index| index + 1);
+		let line_end = text[end..]
+			.find('\n')
+			.map_or(text.len(), |index| end + index);
+		Some((
+			&text[line_start..line_end],
+			(start - line_start)..(end - line_start),
+		))
+	}
+
+	/// Text offsets whose source intersects the given byte range of the source.
+	pub fn text_offsets(&self, source_range: Range<usize>, source: &Source) -> Vec<Range<usize>> {
+		let mut offsets = Vec::<Range<usize>>::new();
+		for (index, (span, range)) in self.chars.iter().enumerate() {
+			let Some((_, range)) = Self::source_range(span, range, source) else {
+				continue;
+			};
+			if range.start >= source_range.end || source_range.start >= range.end {
+				continue;
+			}
+			match offsets.last_mut() {
+				Some(last) if last.end == index => last.end = index + 1,
+				_ => offsets.push(index..index + 1),
+			}
+		}
+		offsets
+	}
 
-		locations
+	fn source_range(
+		span: Span,
+		range: Range<u32>,
+		source: &Source,
+	) -> Option<(SyntaxKind, Range<usize>)> {
+		if span.id()? != source.id() {
+			return None;
+		}
+		let node = source.find(span)?;
+		let start = node.range().start;
+		let range = (start + range.start as usize)..(start + range.end as usize);
+		if node.kind() == SyntaxKind::Text {
+			return Some((node.kind(), range));
+		}
+		// offsets in strings are relative to their value, which only matches the source without escapes,
+		// the chars are mapped like text
+		if node.kind() == SyntaxKind::Str && node.text().contains('\\').not() {
+			return Some((SyntaxKind::Text, (range.start + 1)..(range.end + 1)));
+		}
+		// narrow markup like strong text to the text leaf at the position
+		if range.is_empty().not() {
+			if let Some(leaf) = node.leaf_at(range.start + 1) {
+				if leaf.kind() == SyntaxKind::Text && leaf.range().contains(&range.start) {
+					let end = range.end.min(leaf.range().end);
+					return Some((SyntaxKind::Text, range.start..end));
+				}
+			}
+		}
+		Some((node.kind(), node.range()))
 	}
 
+	/// Language of the text of the chunk, as set with `#set text(lang: ..)`.
 	pub fn short_language(&self) -> &str {
 		self.language.as_str()
 	}
 
+	/// Region of the text of the chunk, as set with `#set text(region: ..)` in its source.
+	pub fn region(&self) -> Option<Region> {
+		self.region
+	}
+
+	/// Language code with the region, like `en-US`, for the variants LanguageTool has rules for.
+	///
+	/// Without a region or for other variants the code of a default region.
 	// https://languagetool.org/http-api/swagger-ui/#!/default/get_languages
 	// defaults to european region codes (maybe).
 	// todo: default to highest population.
 	pub fn long_language(&self) -> String {
+		let dialect = DIALECTS
+			.iter()
+			.find(|(lang, _)| self.language.as_str() == *lang)
+			.zip(self.region)
+			.filter(|((_, regions), region)| regions.contains(&region.as_str()));
+		if let Some(((lang, _), region)) = dialect {
+			return format!("{lang}-{}", region.as_str());
+		}
 		match self.language {
 			Lang::FRENCH => "fr-FR".into(),
 			Lang::SWEDISH => "sv-SE".into(),
@@ -75,147 +342,3141 @@ impl Mapping {
 			Lang::PORTUGUESE => "pt-PT".into(),
 			Lang::ENGLISH => "en-GB".into(),
 			Lang::GERMAN => "de-DE".into(),
-			lang @ _ => lang.as_str().into(),
+			lang => lang.as_str().into(),
+		}
+	}
+}
+
+/// Regions of languages LanguageTool has separate rules for.
+const DIALECTS: &[(&str, &[&str])] = &[
+	("en", &["US", "GB", "AU", "CA", "NZ", "ZA"]),
+	("de", &["DE", "AT", "CH"]),
+	("pt", &["PT", "BR", "AO", "MZ"]),
+	("fr", &["FR", "BE", "CA", "CH"]),
+	("nl", &["NL", "BE"]),
+	("es", &["ES", "AR"]),
+];
+
+/// Sources of the files chars are located in, resolved once per file.
+struct Sources<F> {
+	resolve: F,
+	sources: HashMap<FileId, Option<Source>>,
+	/// Whether the source of a file was not resolved.
+	missing: bool,
+}
+
+impl<F: FnMut(FileId) -> Option<Source>> Sources<F> {
+	fn new(resolve: F) -> Self {
+		Self {
+			resolve,
+			sources: HashMap::new(),
+			missing: false,
 		}
 	}
+
+	fn locate(
+		&mut self,
+		span: Span,
+		range: Range<u32>,
+	) -> Option<(FileId, SyntaxKind, Range<usize>)> {
+		let id = span.id()?;
+		let resolve = &mut self.resolve;
+		let Some(source) = self.sources.entry(id).or_insert_with(|| resolve(id)) else {
+			self.missing = true;
+			return None;
+		};
+		Mapping::source_range(span, range, source).map(|(kind, range)| (id, kind, range))
+	}
 }
 
 const LINE_SPACING: Em = Em::new(0.65);
+const SENTENCE_END: [char; 4] = ['.', '!', '?', '…'];
+const SOFT_HYPHEN: char = '\u{AD}';
+// the public languagetool api rejects longer texts
+const MAX_CHUNK_SIZE: usize = 20_000;
+// bytes repeated from the end of a split chunk at the start of the next one
+const SPLIT_CONTEXT: usize = 200;
+// `// languagetool-ignore-next-line` leaves out the text of the next line
+const IGNORE_NEXT_LINE: &str = "languagetool-ignore-next-line";
+// `#[...] <languagetool-ignore>` leaves out the text of the labeled content
+const IGNORE_LABEL: &str = "languagetool-ignore";
 
-pub fn document(doc: &Document, chunk_size: usize, file_id: FileId) -> Vec<(String, Mapping)> {
-	let mut res = Vec::new();
+/// How the text of a chunk is measured against the chunk size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkStrategy {
+	/// Count chars, which favors scripts with long words.
+	#[default]
+	Chars,
+	/// Count words, with every ideograph and kana as a word of its own.
+	Words,
+}
+
+/// Options for the conversion of a document.
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+	/// Detect columns and convert each column from top to bottom before the next one.
+	pub columns: bool,
+	/// Text inserted instead of inline equations and skipped code or links, block equations are skipped.
+	pub math_placeholder: String,
+	/// Check the strings of equations, like `"km/h"`, instead of the placeholder.
+	///
+	/// The rest of the equation is left out, inline equations without strings keep the placeholder.
+	/// The strings of block equations are a paragraph of their own.
+	pub math_text: bool,
+	/// Skip the text of inline and block code.
+	pub skip_code: bool,
+	/// Skip urls and the text of links.
+	pub skip_links: bool,
+	/// Skip the text of other syntax nodes, like [`SyntaxKind::Ref`], replaced with the placeholder.
+	pub skip_kinds: HashSet<SyntaxKind>,
+	/// Spacing between the lines of a paragraph, should match `#set par(leading: ..)`.
+	pub leading: Em,
+	/// Convert all pages together, so paragraphs can continue on the next page.
+	pub merge_pages: bool,
+	/// End the sentence at manual line breaks, unless the line already ends one.
+	pub linebreak_sentences: bool,
+	/// Replace typographic quotes and apostrophes with their ASCII counterparts.
+	pub ascii_quotes: bool,
+	/// Replace non-breaking spaces, like the ones of `~`, with regular spaces.
+	pub ascii_spaces: bool,
+	/// Keep the whitespace of the source between words, like double spaces or the line breaks
+	/// of wrapped lines, instead of single spaces following the layout.
+	///
+	/// Whitespace from other files than the source of the converter is still a single space.
+	pub faithful_spacing: bool,
+	/// Hard limit for the length of a chunk in bytes, longer paragraphs are split between words.
+	pub max_chunk_size: usize,
+	/// Chars at the end of a chunk repeated as unchecked context at the start of the next one.
+	pub overlap: usize,
+	/// Check the alternative text of images as separate chunks.
+	pub alt_text: bool,
+	/// Check the text of the bibliography as separate chunks, it is left out otherwise.
+	pub bibliography: bool,
+	/// Horizontal distance of text runs still considered adjacent, relative to the text size.
+	pub x_tolerance: Em,
+	/// Deviation from the line spacing still considered the next line, relative to the text size.
+	///
+	/// Fonts like bold variants can have slightly different metrics than the text of the line.
+	pub y_tolerance: Em,
+	/// Measure of the chunk size, a new chunk is started at the next paragraph break after it.
+	pub chunk_strategy: ChunkStrategy,
+	/// Pack whole paragraphs into chunks up to the maximal chunk size, the chunk size of the
+	/// conversion is ignored.
+	///
+	/// A chunk ends before the paragraph that would exceed the limit, so the text is checked
+	/// in fewer requests than when splitting at the first paragraph break after the chunk size.
+	pub pack_paragraphs: bool,
+	/// Decides how the text of consecutive runs is joined.
+	///
+	/// With `None` a [`DefaultJoin`] with the tolerances and `linebreak_sentences` of these options.
+	pub join: Option<Arc<dyn JoinPolicy>>,
+	/// Zero based indices of the pages to convert, all pages with `None`.
+	pub pages: Option<Range<usize>>,
+	/// Compute the [`Mapping::hash`] of every chunk returned by [`document`].
+	pub hash_chunks: bool,
+}
 
-	for page in &doc.pages {
-		let mut converter = Converter::new(chunk_size, Lang::ENGLISH);
-		converter.frame(&page.frame, Point::zero(), &mut res, file_id);
-		if converter.contains_file {
-			res.push((converter.text, converter.mapping));
+impl Default for ConvertOptions {
+	fn default() -> Self {
+		Self {
+			columns: false,
+			math_placeholder: "0".into(),
+			math_text: false,
+			skip_code: true,
+			skip_links: true,
+			skip_kinds: HashSet::new(),
+			leading: LINE_SPACING,
+			merge_pages: false,
+			linebreak_sentences: false,
+			ascii_quotes: false,
+			ascii_spaces: false,
+			faithful_spacing: false,
+			alt_text: false,
+			bibliography: false,
+			x_tolerance: Em::new(0.01),
+			y_tolerance: Em::new(0.05),
+			max_chunk_size: MAX_CHUNK_SIZE,
+			overlap: 0,
+			chunk_strategy: ChunkStrategy::Chars,
+			pack_paragraphs: false,
+			join: None,
+			pages: None,
+			hash_chunks: false,
+		}
+	}
+}
+
+macro_rules! setters {
+	($($setter:ident $field:ident: $ty:ty),* $(,)?) => {
+		$(
+			#[doc = concat!("Sets [`Self::", stringify!($field), "`].")]
+			pub fn $setter(mut self, $field: $ty) -> Self {
+				self.$field = $field;
+				self
+			}
+		)*
+	};
+}
+
+/// Setters to build the options from the defaults, so new options do not break callers.
+impl ConvertOptions {
+	setters! {
+		with_columns columns: bool,
+		with_math_text math_text: bool,
+		with_skip_code skip_code: bool,
+		with_skip_links skip_links: bool,
+		with_leading leading: Em,
+		with_merge_pages merge_pages: bool,
+		with_linebreak_sentences linebreak_sentences: bool,
+		with_ascii_quotes ascii_quotes: bool,
+		with_ascii_spaces ascii_spaces: bool,
+		with_faithful_spacing faithful_spacing: bool,
+		with_max_chunk_size max_chunk_size: usize,
+		with_overlap overlap: usize,
+		with_alt_text alt_text: bool,
+		with_bibliography bibliography: bool,
+		with_x_tolerance x_tolerance: Em,
+		with_y_tolerance y_tolerance: Em,
+		with_chunk_strategy chunk_strategy: ChunkStrategy,
+		with_pack_paragraphs pack_paragraphs: bool,
+		with_hash_chunks hash_chunks: bool,
+	}
+
+	/// Sets [`Self::math_placeholder`].
+	pub fn with_math_placeholder(mut self, math_placeholder: impl Into<String>) -> Self {
+		self.math_placeholder = math_placeholder.into();
+		self
+	}
+
+	/// Sets [`Self::skip_kinds`].
+	pub fn with_skip_kinds(mut self, skip_kinds: impl IntoIterator<Item = SyntaxKind>) -> Self {
+		self.skip_kinds = skip_kinds.into_iter().collect();
+		self
+	}
+
+	/// Sets [`Self::join`].
+	pub fn with_join(mut self, join: impl JoinPolicy + 'static) -> Self {
+		self.join = Some(Arc::new(join));
+		self
+	}
+
+	/// Sets [`Self::pages`].
+	pub fn with_pages(mut self, pages: Range<usize>) -> Self {
+		self.pages = Some(pages);
+		self
+	}
+}
+
+/// The chunks of the text of the file of the source in the document.
+///
+/// Fails with [`Error::Conversion`] if the options are invalid, like a page range ending before
+/// its start.
+pub fn document(
+	doc: &Document,
+	chunk_size: usize,
+	source: &Source,
+	options: &ConvertOptions,
+) -> Result<Vec<(String, Mapping)>, Error> {
+	let mut chunks = convert(doc, chunk_size, source, options, false)?;
+	hash_chunks(&mut chunks, options);
+	Ok(chunks)
+}
+
+/// The conversion of earlier versions, without the source and with the default options.
+///
+/// Without the syntax of the source, text like code or links is not skipped.
+pub fn document_file(doc: &Document, chunk_size: usize, file_id: FileId) -> Vec<(String, Mapping)> {
+	let options = ConvertOptions::default();
+	let pages = doc.pages.iter().map(|page| {
+		let mut converter = Converter::with_options(Some(chunk_size), options.clone());
+		converter.push_frame(&page.frame, Point::zero(), file_id);
+		converter.finish()
+	});
+	let mut chunks = join_pages(pages);
+	if let Some((_, last)) = chunks.last_mut() {
+		last.info.ends_mid_sentence = false;
+	}
+	chunks
+}
+
+/// The chunks of the document, without a mapping to the source if `text_only`.
+fn convert(
+	doc: &Document,
+	chunk_size: usize,
+	source: &Source,
+	options: &ConvertOptions,
+	text_only: bool,
+) -> Result<Vec<(String, Mapping)>, Error> {
+	if let Some(range) = options
+		.pages
+		.as_ref()
+		.filter(|range| range.start > range.end)
+	{
+		return Err(Error::Conversion(format!(
+			"invalid page range {}..{}",
+			range.start, range.end
+		)));
+	}
+	let mut chunks = if options.merge_pages {
+		let mut converter = converter(chunk_size, source, options, text_only);
+		for page in selected_pages(doc, options) {
+			converter.flow_break = true;
+			converter.push_frame(&page.frame, Point::zero(), source.id());
+		}
+		converter.finish()
+	} else {
+		#[cfg(feature = "rayon")]
+		let chunks = pages_parallel(doc, chunk_size, source, options, text_only);
+		#[cfg(not(feature = "rayon"))]
+		let chunks = pages(doc, chunk_size, source, options, text_only);
+		chunks
+	};
+	// the end of the document ends the last sentence
+	if let Some((_, last)) = chunks.last_mut() {
+		last.info.ends_mid_sentence = false;
+	}
+	Ok(chunks)
+}
+
+fn converter(
+	chunk_size: usize,
+	source: &Source,
+	options: &ConvertOptions,
+	text_only: bool,
+) -> Converter {
+	let converter =
+		Converter::with_options(Some(chunk_size), options.clone()).with_source(source.clone());
+	match text_only {
+		true => converter.text_only(),
+		false => converter,
+	}
+}
+
+/// Like [`document`], but falls back to the [`markup`] of the source without a compiled
+/// document or when its layout contains no text of the file.
+pub fn document_or_markup(
+	doc: Option<&Document>,
+	chunk_size: usize,
+	source: &Source,
+	options: &ConvertOptions,
+) -> Result<Vec<(String, Mapping)>, Error> {
+	let chunks = match doc {
+		Some(doc) => document(doc, chunk_size, source, options)?,
+		None => Vec::new(),
+	};
+	if chunks.is_empty().not() {
+		return Ok(chunks);
+	}
+	let mut chunks = markup(source, chunk_size, options);
+	hash_chunks(&mut chunks, options);
+	Ok(chunks)
+}
+
+fn hash_chunks(chunks: &mut [(String, Mapping)], options: &ConvertOptions) {
+	if options.hash_chunks {
+		for (text, mapping) in chunks {
+			mapping.hash = Some(chunk_hash(text, mapping.language));
+		}
+	}
+}
+
+/// FNV-1a hash of the language and text, the same for every build and platform.
+fn chunk_hash(text: &str, language: Lang) -> u64 {
+	let mut hasher = Fnv::default();
+	hasher.write(language.as_str().as_bytes());
+	// the separator keeps the language and text apart
+	hasher.write(&[0]);
+	hasher.write(text.as_bytes());
+	hasher.finish()
+}
+
+/// FNV-1a hasher, unlike the [`std::collections::hash_map::DefaultHasher`] the same for every build.
+pub(crate) struct Fnv(u64);
+
+impl Default for Fnv {
+	fn default() -> Self {
+		Self(0xcbf2_9ce4_8422_2325)
+	}
+}
+
+impl Hasher for Fnv {
+	fn write(&mut self, bytes: &[u8]) {
+		for byte in bytes {
+			self.0 ^= u64::from(*byte);
+			self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+		}
+	}
+
+	fn finish(&self) -> u64 {
+		self.0
+	}
+}
+
+/// Summary of a converted document, to tell documents without pages or checked text apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+	/// Number of processed pages.
+	pub pages: usize,
+	/// Whether any page contained visible text of the file.
+	pub contains_file: bool,
+}
+
+/// Summarizes the chunks [`document`] returned for the document.
+pub fn summary(doc: &Document, chunks: &[(String, Mapping)]) -> Summary {
+	Summary {
+		pages: doc.pages.len(),
+		// only chunks with text of the file are returned
+		contains_file: chunks.is_empty().not(),
+	}
+}
+
+/// The pages of the document in the page range of the options, clamped to the document.
+fn selected_pages<'a>(doc: &'a Document, options: &ConvertOptions) -> &'a [Page] {
+	let Some(range) = &options.pages else {
+		return &doc.pages;
+	};
+	let end = range.end.min(doc.pages.len());
+	&doc.pages[range.start.min(end)..end]
+}
+
+fn page(
+	page: &Page,
+	chunk_size: usize,
+	source: &Source,
+	options: &ConvertOptions,
+	text_only: bool,
+) -> Vec<(String, Mapping)> {
+	let mut converter = converter(chunk_size, source, options, text_only);
+	converter.push_frame(&page.frame, Point::zero(), source.id());
+	converter.finish()
+}
+
+#[cfg_attr(feature = "rayon", allow(unused))]
+fn pages(
+	doc: &Document,
+	chunk_size: usize,
+	source: &Source,
+	options: &ConvertOptions,
+	text_only: bool,
+) -> Vec<(String, Mapping)> {
+	join_pages(
+		selected_pages(doc, options)
+			.iter()
+			.map(|p| page(p, chunk_size, source, options, text_only)),
+	)
+}
+
+#[cfg(feature = "rayon")]
+fn pages_parallel(
+	doc: &Document,
+	chunk_size: usize,
+	source: &Source,
+	options: &ConvertOptions,
+	text_only: bool,
+) -> Vec<(String, Mapping)> {
+	use rayon::prelude::*;
+
+	let pages: Vec<_> = selected_pages(doc, options)
+		.par_iter()
+		.map(|p| page(p, chunk_size, source, options, text_only))
+		.collect();
+	join_pages(pages.into_iter())
+}
+
+/// Joins the chunks of the pages, the first chunk of a page continues a sentence split by the
+/// page break.
+fn join_pages(pages: impl Iterator<Item = Vec<(String, Mapping)>>) -> Vec<(String, Mapping)> {
+	let mut chunks: Vec<(String, Mapping)> = Vec::new();
+	for page in pages {
+		let ended = chunks
+			.last()
+			.map(|(_, mapping)| mapping.info.ends_mid_sentence);
+		let start = chunks.len();
+		chunks.extend(page);
+		if let (Some(ended), Some((_, first))) = (ended, chunks.get_mut(start)) {
+			first.info.starts_mid_sentence |= ended;
+		}
+	}
+	chunks
+}
+
+/// Removes suggestions with the same message at the same source location,
+/// like a typo in a header repeated on every page.
+pub fn dedup_suggestions<'a>(
+	suggestions: Vec<(Suggestion, &'a Mapping)>,
+	source: &Source,
+) -> Vec<(Suggestion, &'a Mapping)> {
+	let mut seen = HashSet::new();
+	suggestions
+		.into_iter()
+		.filter(|(suggestion, mapping)| {
+			let locations = mapping.location(suggestion, source);
+			locations.is_empty() || seen.insert((locations, suggestion.message.clone()))
+		})
+		.collect()
+}
+
+/// Reasons why a replacement can not be applied to the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyError {
+	/// The suggestion is not located in the source.
+	NoLocation,
+	/// The suggestion is located in multiple disjoint ranges of the source.
+	Disjoint,
+}
+
+impl std::fmt::Display for ApplyError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::NoLocation => write!(f, "suggestion is not located in the source"),
+			Self::Disjoint => write!(f, "suggestion spans disjoint ranges of the source"),
 		}
 	}
-	res
 }
 
-struct Converter {
+impl std::error::Error for ApplyError {}
+
+/// The edit of the source replacing the text of the suggestion.
+///
+/// Suggestions without any located char, see [`Resolution::Nearest`], are not applied.
+pub fn apply_suggestion(
+	suggestion: &Suggestion,
+	replacement: &str,
+	mapping: &Mapping,
+	source: &Source,
+) -> Result<(Range<usize>, String), ApplyError> {
+	match mapping.location_resolution(suggestion, source) {
+		(_, Resolution::Nearest) => Err(ApplyError::NoLocation),
+		(locations, _) => apply_locations(&locations, replacement),
+	}
+}
+
+/// The edit replacing the text at the locations of a suggestion.
+pub fn apply_locations(
+	locations: &[Range<usize>],
+	replacement: &str,
+) -> Result<(Range<usize>, String), ApplyError> {
+	match locations {
+		[] => Err(ApplyError::NoLocation),
+		[range] => Ok((range.clone(), replacement.into())),
+		_ => Err(ApplyError::Disjoint),
+	}
+}
+
+/// Like [`document`], but only keeps chunks touching the byte range of the source.
+pub fn document_range(
+	doc: &Document,
+	chunk_size: usize,
+	source: &Source,
+	range: Range<usize>,
+	options: &ConvertOptions,
+) -> Result<Vec<(String, Mapping)>, Error> {
+	let mut res = document(doc, chunk_size, source, options)?;
+	res.retain(|(_, mapping)| mapping.text_offsets(range.clone(), source).is_empty().not());
+	Ok(res)
+}
+
+/// Like [`document`], but only returns the text of the chunks.
+///
+/// Faster than [`document`], as the converter does not map the text back to the source.
+pub fn document_text(
+	doc: &Document,
+	chunk_size: usize,
+	source: &Source,
+	options: &ConvertOptions,
+) -> Result<Vec<String>, Error> {
+	let chunks = convert(doc, chunk_size, source, options, true)?;
+	Ok(chunks.into_iter().map(|(text, _)| text).collect())
+}
+
+/// Linearizes laid out frames into text chunks with a [`Mapping`] back to the source.
+///
+/// This is the conversion used by [`document`], which runs one converter per page.
+pub struct Converter {
 	text: String,
 	mapping: Mapping,
 	x: Abs,
 	y: Abs,
-	span: (Span, u16),
-	chunk_size: usize,
+	size: Abs,
+	span: (Span, u32),
+	chunk_size: Option<usize>,
+	// byte index of the last paragraph break of the text, where packed paragraphs are cut
+	parbreak: Option<usize>,
 	contains_file: bool,
+	flow_break: bool,
+	hyphenated: bool,
+	math: Option<(Span, Point, Point)>,
+	// area of skipped text without the span of the skipped node, like urls or references
+	link: Option<(Span, Point, Point)>,
+	caption: Option<Span>,
+	bibliography: Option<Span>,
+	part: Option<Span>,
+	heading: Option<Span>,
+	source: Option<Source>,
+	skipped: Option<(Span, Option<Span>)>,
+	skipping: Option<Span>,
+	ignored_lines: HashSet<usize>,
+	regions: Vec<(Range<usize>, Option<Region>)>,
+	ignored: Option<(Span, bool)>,
+	mid_sentence: bool,
+	policy: Arc<dyn JoinPolicy>,
+	options: ConvertOptions,
+	res: Vec<(String, Mapping)>,
 }
 
 impl Converter {
-	fn new(chunk_size: usize, language: Lang) -> Self {
+	/// Create a new converter.
+	///
+	/// A new chunk is started at the next paragraph break after `chunk_size` chars.
+	/// With `None` or a size of 0 all text is kept in a single chunk.
+	pub fn new(chunk_size: Option<usize>) -> Self {
+		Self::with_options(chunk_size, ConvertOptions::default())
+	}
+
+	/// Create a new converter with additional options.
+	pub fn with_options(chunk_size: Option<usize>, options: ConvertOptions) -> Self {
 		Self {
 			text: String::new(),
-			mapping: Mapping { chars: Vec::new(), language },
+			mapping: Mapping::new(Lang::ENGLISH),
 			x: Abs::zero(),
 			y: Abs::zero(),
+			size: Abs::zero(),
 			span: (Span::detached(), 0),
+			chunk_size: chunk_size.filter(|&chunk_size| chunk_size != 0),
+			parbreak: None,
 			contains_file: false,
-			chunk_size,
+			flow_break: false,
+			hyphenated: false,
+			math: None,
+			link: None,
+			caption: None,
+			bibliography: None,
+			part: None,
+			heading: None,
+			source: None,
+			skipped: None,
+			skipping: None,
+			ignored_lines: HashSet::new(),
+			regions: Vec::new(),
+			ignored: None,
+			mid_sentence: false,
+			policy: options
+				.join
+				.clone()
+				.unwrap_or_else(|| Arc::new(DefaultJoin::new(&options))),
+			options,
+			res: Vec::new(),
 		}
 	}
 
+	/// Only convert the text, the chunks are returned with an empty mapping to the source.
+	pub fn text_only(mut self) -> Self {
+		self.mapping.chars = Chars::text_only();
+		self
+	}
+
+	/// Use the syntax of the source to skip text like code.
+	pub fn with_source(mut self, source: Source) -> Self {
+		self.ignored_lines = ignored_lines(&source);
+		self.regions = regions(&source);
+		self.source = Some(source);
+		self
+	}
+
+	/// Convert the text in `frame` placed at `pos`.
+	///
+	/// Only chunks containing text from `file_id` are kept.
+	pub fn push_frame(&mut self, frame: &Frame, pos: Point, file_id: FileId) {
+		self.frame(frame, pos, file_id);
+	}
+
+	/// Finish the current chunk and return all chunks.
+	pub fn finish(mut self) -> Vec<(String, Mapping)> {
+		self.seperate();
+		self.res
+	}
+
 	fn insert_space(&mut self) {
 		self.text += " ";
 		self.mapping.chars.push((Span::detached(), 0..0));
 	}
 
-	fn seperate(&mut self, res: &mut Vec<(String, Mapping)>) {
-		let language = self.mapping.language;
+	fn seperate(&mut self) {
+		self.seperate_chunk(self.ends_sentence().not());
+	}
+
+	/// Finishes the current chunk, `mid_sentence` if its last sentence continues in the next one.
+	fn seperate_chunk(&mut self, mid_sentence: bool) {
+		// the buffers of the next chunk start with the capacity of this one
+		let capacity = self.text.capacity();
+		let text = std::mem::replace(&mut self.text, String::with_capacity(capacity));
+		let chars = self.mapping.chars.take();
+		let mut mapping = self.mapping.chunk(chars, ChunkInfo::default());
+		self.mapping.context = 0;
+		// chunks without text do not split a sentence
+		if text.trim().is_empty().not() {
+			mapping.info = ChunkInfo {
+				starts_mid_sentence: self.mid_sentence,
+				ends_mid_sentence: mid_sentence,
+			};
+			self.mid_sentence = mid_sentence;
+		}
 		if self.contains_file {
-			let text = std::mem::take(&mut self.text);
-			let mapping = std::mem::replace(
-				&mut self.mapping,
-				Mapping {
-					chars: Vec::new(),
-					language: Lang::ENGLISH,
-				},
-			);
-			res.push((text, mapping));
+			self.res.push((text, mapping));
 		}
-		*self = Converter::new(self.chunk_size, language);
+		self.x = Abs::zero();
+		self.y = Abs::zero();
+		self.size = Abs::zero();
+		self.span = (Span::detached(), 0);
+		self.contains_file = false;
+		self.hyphenated = false;
+		self.parbreak = None;
 	}
 
-	fn insert_parbreak(&mut self, res: &mut Vec<(String, Mapping)>) {
-		if self.mapping.chars.len() > self.chunk_size {
-			self.seperate(res);
+	/// Cuts the text at the last paragraph break once it exceeds the maximal chunk size.
+	///
+	/// The next chunk starts with the paragraph after the break.
+	fn pack(&mut self, file_id: FileId) {
+		if self.text.len() <= self.options.max_chunk_size {
+			return;
+		}
+		let Some(end) = self.parbreak.take().filter(|&end| end > 0) else {
 			return;
+		};
+		let start = self.context_start(end);
+		let chunk_end = crate::utf_16_index(&self.text, end);
+		let context_start = crate::utf_16_index(&self.text, start);
+		let chunk = self.text[..end].to_owned();
+		let chars = self.mapping.chars.slice(0..chunk_end);
+		// the paragraph break is only kept to separate the context from the next paragraph
+		let skip = if start == end { 2 } else { 0 };
+		let mut rest = self.mapping.chars.slice(context_start..chunk_end);
+		rest.extend(
+			self.mapping
+				.chars
+				.range(chunk_end + skip..self.mapping.chars.len()),
+		);
+		let text = self.text[start..end].to_owned() + &self.text[end + skip..];
+		let info = ChunkInfo {
+			starts_mid_sentence: self.mid_sentence,
+			ends_mid_sentence: false,
+		};
+		self.mid_sentence = false;
+		if self.contains(&chars, file_id) {
+			self.res.push((chunk, self.mapping.chunk(chars, info)));
+		}
+		self.mapping.context = match start == end {
+			true => 0,
+			false => chunk_end - context_start + 2,
+		};
+		self.text = text;
+		self.contains_file = self.contains(&rest, file_id);
+		self.mapping.chars = rest;
+	}
+
+	/// Whether the chars contain text of the file, chars without spans keep the state of the
+	/// current chunk.
+	fn contains(&self, chars: &Chars, file_id: FileId) -> bool {
+		if chars.is_text_only() {
+			return self.contains_file;
+		}
+		chars.iter().any(|(span, _)| span.id() == Some(file_id))
+	}
+
+	/// Splits the text between words until it is within the maximal chunk size.
+	///
+	/// The next chunk starts with the last words of the sentence as context.
+	fn split(&mut self, file_id: FileId) {
+		while self.text.len() > self.options.max_chunk_size {
+			let limit = match floor_char_boundary(&self.text, self.options.max_chunk_size) {
+				0 => self.text.chars().next().map_or(0, char::len_utf8),
+				limit => limit,
+			};
+			let boundary = match self.text[..limit].rfind(' ') {
+				Some(boundary) if boundary > 0 => boundary,
+				_ => limit,
+			};
+			let min = floor_char_boundary(&self.text, boundary.saturating_sub(SPLIT_CONTEXT));
+			let context = &self.text[min..boundary];
+			let start = match context.rfind(SENTENCE_END) {
+				Some(end) => min + end + 1,
+				None => min + context.find(' ').map_or(context.len(), |start| start + 1),
+			};
+			let rest = self.text[start..].trim_start();
+			let start = if rest.len() == self.text.len() {
+				boundary
+			} else {
+				self.text.len() - rest.len()
+			};
+
+			let chunk_end = crate::utf_16_index(&self.text, boundary);
+			let rest_start = crate::utf_16_index(&self.text, start);
+			let chunk = self.text[..boundary].to_owned();
+			self.text.drain(..start);
+			let chars = self.mapping.chars.slice(0..chunk_end);
+			self.mapping.chars.remove_start(rest_start);
+			let info = ChunkInfo {
+				starts_mid_sentence: self.mid_sentence,
+				ends_mid_sentence: chunk.trim_end().ends_with(SENTENCE_END).not(),
+			};
+			self.mid_sentence = info.ends_mid_sentence;
+			if self.contains_file {
+				self.res.push((chunk, self.mapping.chunk(chars, info)));
+			}
+			self.mapping.context = chunk_end - rest_start;
+			self.contains_file = self.contains(&self.mapping.chars, file_id);
+		}
+	}
+
+	/// Start of the overlap before `end` of the text, at a word boundary.
+	fn context_start(&self, end: usize) -> usize {
+		let mut utf_16 = 0;
+		let mut start = end;
+		for (index, c) in self.text[..end].char_indices().rev() {
+			utf_16 += c.len_utf16();
+			if utf_16 > self.options.overlap {
+				break;
+			}
+			if c.is_whitespace() {
+				start = index + c.len_utf8();
+			}
+		}
+		start
+	}
+
+	fn ends_sentence(&self) -> bool {
+		self.text.trim_end().ends_with(SENTENCE_END)
+	}
+
+	fn chunk_len(&self) -> usize {
+		match self.options.chunk_strategy {
+			ChunkStrategy::Chars => self.mapping.chars.len(),
+			ChunkStrategy::Words => word_count(&self.text),
 		}
+	}
+
+	/// Ends the sentence like a paragraph break, without starting a new chunk.
+	fn insert_sentence_break(&mut self) {
 		self.text += "\n\n";
 		self.mapping.chars.push((Span::detached(), 0..0));
 		self.mapping.chars.push((Span::detached(), 0..0));
 	}
 
-	fn whitespace(&mut self, text: &TextItem, pos: Point, res: &mut Vec<(String, Mapping)>) {
-		if self.x.approx_eq(pos.x) {
-			return;
-		}
-		let line_spacing = (text.font.metrics().cap_height + LINE_SPACING).at(text.size);
-		let next_line = (self.y + line_spacing).approx_eq(pos.y);
-		if !next_line {
-			self.insert_parbreak(res);
+	fn insert_parbreak(&mut self) {
+		if self.options.pack_paragraphs {
+			self.parbreak = Some(self.text.len());
+			self.insert_sentence_break();
 			return;
 		}
-		let span = text.glyphs[0].span;
-		if span == self.span {
+		if self
+			.chunk_size
+			.is_some_and(|chunk_size| self.chunk_len() > chunk_size)
+		{
+			let start = self.context_start(self.text.len());
+			let context = self.text[start..].to_owned();
+			let chars = self
+				.mapping
+				.chars
+				.slice(crate::utf_16_index(&self.text, start)..self.mapping.chars.len());
+			// paragraph breaks end the sentence
+			self.seperate_chunk(false);
+			if context.is_empty() {
+				return;
+			}
+			self.text += &context;
+			self.mapping.chars.extend(chars.iter());
+			self.insert_sentence_break();
+			self.mapping.context = self.mapping.chars.len();
 			return;
 		}
-		self.insert_space();
+		self.insert_sentence_break();
 	}
 
-	fn frame(
+	/// Checks if the text is a smaller run within the current line, like a sub- or superscript.
+	///
+	/// The lines next to a drop cap are not shifted, they keep their own baseline.
+	fn shifted(&self, text: &TextItem, pos: Point) -> bool {
+		text.size < self.size
+			&& (pos.y - self.y).abs() < self.size / 2.0
+			&& join::is_drop_cap(&self.text, self.size, text.size).not()
+	}
+
+	/// Joins the text with the next run of text, as decided by the join policy.
+	fn join(
 		&mut self,
-		frame: &typst::layout::Frame,
+		text: &TextItem,
 		pos: Point,
-		res: &mut Vec<(String, Mapping)>,
+		shifted: bool,
+		(part, heading): (Option<Span>, Option<Span>),
 		file_id: FileId,
 	) {
-		for &(p, ref item) in frame.items() {
-			self.item(p + pos, item, res, file_id);
+		// zero width joiners or fully substituted text can be without glyphs
+		let Some(glyph) = text.glyphs.first() else {
+			return;
+		};
+		// typst reports offsets past `u16::MAX` as 0
+		let unknown_offset = glyph.span == (self.span.0, 0) && self.span.1 > u32::from(u16::MAX);
+		let linebreak = self.linebreak();
+		let prev = Context {
+			text: &self.text,
+			pos: Point::new(self.x, self.y),
+			size: self.size,
+			heading: self.heading,
+			part: self.part,
+			hyphenated: self.hyphenated,
+			linebreak: linebreak.is_some(),
+			flow_break: false,
+			shifted: false,
+			continues: false,
+			line_spacing: Abs::zero(),
+		};
+		let next = Context {
+			text: &text.text,
+			pos,
+			size: text.size,
+			heading,
+			part,
+			hyphenated: false,
+			linebreak: false,
+			flow_break: std::mem::take(&mut self.flow_break),
+			shifted,
+			continues: unknown_offset.not() && (glyph.span.0, u32::from(glyph.span.1)) == self.span,
+			line_spacing: (text.font.metrics().cap_height + self.options.leading).at(text.size),
+		};
+		let join = self.policy.join(&prev, &next);
+		if unknown_offset && join != Join::None {
+			// the real offset is unknown, assume the line was wrapped at a single space
+			self.span.1 += 1;
+		}
+		match join {
+			Join::None => {},
+			Join::Space => {
+				let start = (glyph.span.0, self.offset(glyph.span));
+				if let Some(space) = self.source_gap(start) {
+					self.push_space(space, file_id);
+				} else {
+					self.insert_space();
+				}
+			},
+			Join::LineBreak | Join::SentenceBreak | Join::ParBreak => {
+				if self.text.ends_with(' ') {
+					self.text.pop();
+					self.mapping.chars.pop();
+				}
+				match join {
+					Join::ParBreak => self.insert_parbreak(),
+					Join::SentenceBreak => self.insert_sentence_break(),
+					_ => self.insert_linebreak(linebreak, file_id),
+				}
+			},
+		}
+	}
+
+	/// The whitespace node of the source from the end of the last text to the start of the
+	/// next one, for [`ConvertOptions::faithful_spacing`].
+	fn source_gap(&self, (span, offset): (Span, u32)) -> Option<(Span, String)> {
+		let source = self
+			.source
+			.as_ref()
+			.filter(|_| self.options.faithful_spacing)?;
+		let end = source.range(self.span.0)?.start + self.span.1 as usize;
+		let start = source.range(span)?.start + offset as usize;
+		let leaf = LinkedNode::new(source.root()).leaf_at(end + 1)?;
+		(leaf.kind() == SyntaxKind::Space && leaf.range() == (end..start))
+			.then(|| (leaf.span(), leaf.text().to_string()))
+	}
+
+	/// The whitespace node of the source laid out as a single space, for
+	/// [`ConvertOptions::faithful_spacing`].
+	fn source_space(&self, span: Span) -> Option<(Span, String)> {
+		let source = self
+			.source
+			.as_ref()
+			.filter(|_| self.options.faithful_spacing)?;
+		let node = source.find(span)?;
+		(node.kind() == SyntaxKind::Space).then(|| (span, node.text().to_string()))
+	}
+
+	/// Appends the text of the whitespace node, mapped to the node.
+	fn push_space(&mut self, (span, text): (Span, String), file_id: FileId) {
+		for (index, c) in text.char_indices() {
+			self.text.push(c);
+			let start = index as u32;
+			for _ in 0..c.len_utf16() {
+				self.mapping
+					.chars
+					.push((span, start..start + c.len_utf8() as u32));
+			}
+		}
+		self.span = (span, text.len() as u32);
+		if span.id() == Some(file_id) {
+			self.contains_file = true;
+		}
+	}
+
+	/// Appends a newline, mapped to the manual line break of the source.
+	fn insert_linebreak(&mut self, linebreak: Option<(Span, u32)>, file_id: FileId) {
+		let (span, len) = linebreak.unwrap_or((Span::detached(), 0));
+		self.text.push('\n');
+		self.mapping.chars.push((span, 0..len));
+		if span.id() == Some(file_id) {
+			self.contains_file = true;
+		}
+	}
+
+	/// The manual line break after the syntax node at the end of the last text, with the length
+	/// of its node.
+	fn linebreak(&self) -> Option<(Span, u32)> {
+		let source = self.source.as_ref()?;
+		let (span, end) = self.span;
+		if span.id() != Some(source.id()) {
+			return None;
+		}
+		let node = source
+			.find(span)
+			.filter(|node| node.len() == end as usize)?;
+		let mut next = node.next_sibling();
+		if next
+			.as_ref()
+			.is_some_and(|next| next.kind() == SyntaxKind::Hash)
+		{
+			next = next.and_then(|next| next.next_sibling());
+		}
+		next.filter(|next| next.kind() == SyntaxKind::Linebreak || is_call(next, "linebreak"))
+			.map(|next| (next.span(), next.len() as u32))
+	}
+
+	/// Checks if `pos` is inside the current equation, ends the equation if not.
+	fn in_math(&mut self, pos: Point) -> bool {
+		let Some((_, min, max)) = self.math else {
+			return false;
+		};
+		let inside_x = min.x <= pos.x && pos.x < max.x && pos.x.approx_eq(max.x).not();
+		if inside_x && (min.y..=max.y).contains(&pos.y) {
+			return true;
 		}
+		self.math = None;
+		false
 	}
 
-	fn item(
+	/// The skipped node of the current link if `pos` is inside it, ends the link if not.
+	///
+	/// The node of urls is detached.
+	fn in_link(&mut self, pos: Point) -> Option<Span> {
+		let (span, min, max) = self.link?;
+		let inside_x = min.x <= pos.x && pos.x < max.x && pos.x.approx_eq(max.x).not();
+		if inside_x && (min.y..=max.y).contains(&pos.y) {
+			return Some(span);
+		}
+		self.link = None;
+		None
+	}
+
+	fn equation(
 		&mut self,
+		equation: &Packed<EquationElem>,
 		pos: Point,
-		item: &typst::layout::FrameItem,
-		res: &mut Vec<(String, Mapping)>,
+		size: Size,
 		file_id: FileId,
 	) {
-		use typst::introspection::Meta as M;
-		use typst::layout::FrameItem as I;
-		match item {
-			I::Group(g) => self.frame(&g.frame, pos, res, file_id),
-			I::Text(t) => {
-				if self.mapping.language != t.lang {
-					self.seperate(res);
-				}
-				self.mapping.language = t.lang;
+		if size.x.approx_eq(Abs::zero()) || size.y.approx_eq(Abs::zero()) {
+			return;
+		}
+		let (min, max) = (pos, pos + size.to_point());
+		let span = equation.span();
+		if let Some((current, start, end)) = &mut self.math {
+			// nested elements of the equation are marked as well
+			if min.x <= end.x && start.x <= max.x && min.y <= end.y && start.y <= max.y {
+				*start = start.min(min);
+				*end = end.max(max);
+				return;
+			}
+			// inline equations are split at possible line breaks
+			if *current == span {
+				(*start, *end) = (min, max);
+				self.x = max.x;
+				return;
+			}
+		}
+		self.math = Some((span, min, max));
+		let block = equation.block(StyleChain::default());
+		let strings = self.math_strings(span);
+		if block && strings.is_empty() {
+			return;
+		}
+
+		if block && self.text.is_empty().not() {
+			// the strings of a block equation are a paragraph of their own
+			if self.text.ends_with(' ') {
+				self.text.pop();
+				self.mapping.chars.pop();
+			}
+			self.insert_parbreak();
+		}
+		let adjacent = close(self.x, pos.x, self.options.x_tolerance.at(self.size));
+		if block.not() && adjacent.not() && self.text.is_empty().not() {
+			self.insert_space();
+		}
+		if span.id() == Some(file_id) {
+			self.contains_file = true;
+		}
+		if strings.is_empty() {
+			self.insert_placeholder(span);
+		}
+		for (index, (span, text)) in strings.into_iter().enumerate() {
+			if index > 0 {
+				self.insert_space();
+			}
+			self.text += &text;
+			for (index, c) in text.char_indices() {
+				for _ in 0..c.len_utf16() {
+					self.mapping
+						.chars
+						.push((span, index as u32..(index + c.len_utf8()) as u32));
+				}
+			}
+		}
+		if block {
+			self.y = max.y;
+		}
+		self.x = max.x;
+		self.span = (Span::detached(), 0);
+	}
+
+	/// Adds the alternative text of an image as a separate chunk.
+	fn alt_text(&mut self, alt: &str, span: Span, file_id: FileId) {
+		// map the text to the `alt` argument if possible, otherwise to the whole image
+		let node = self.source.as_ref().and_then(|source| source.find(span));
+		let argument = node
+			.as_ref()
+			.and_then(|node| node.cast::<ast::FuncCall>())
+			.and_then(|call| {
+				call.args().items().find_map(|arg| match arg {
+					ast::Arg::Named(named) if named.name().as_str() == "alt" => Some(named.expr()),
+					_ => None,
+				})
+			})
+			.filter(|expr| matches!(expr, ast::Expr::Str(str) if str.get() == alt))
+			.map(|expr| expr.span());
+		self.seperate();
+		self.text += alt;
+		for (index, c) in alt.char_indices() {
+			let m = match argument {
+				Some(span) => (span, index as u32..(index + c.len_utf8()) as u32),
+				None => (span, 0..0),
+			};
+			for _ in 0..c.len_utf16() {
+				self.mapping.chars.push(m.clone());
+			}
+		}
+		self.contains_file = argument.unwrap_or(span).id() == Some(file_id);
+		self.seperate();
+	}
+
+	/// The string literals of the equation with their spans, for [`ConvertOptions::math_text`].
+	fn math_strings(&self, span: Span) -> Vec<(Span, String)> {
+		let Some(node) = self
+			.source
+			.as_ref()
+			.filter(|_| self.options.math_text)
+			.and_then(|source| source.find(span))
+		else {
+			return Vec::new();
+		};
+		let mut strings = Vec::new();
+		let mut nodes = vec![node];
+		while let Some(node) = nodes.pop() {
+			if let Some(str) = node.cast::<ast::Str>() {
+				strings.push((node.span(), str.get().to_string()));
+			}
+			// the children are popped in the order of the source
+			nodes.extend(node.children().rev());
+		}
+		strings
+	}
+
+	fn insert_placeholder(&mut self, span: Span) {
+		self.text += &self.options.math_placeholder;
+		for _ in self.options.math_placeholder.encode_utf16() {
+			self.mapping.chars.push((span, 0..0));
+		}
+	}
+
+	/// The syntax node the text at the span is skipped with.
+	fn skipped(&mut self, span: Span) -> Option<Span> {
+		let source = self.source.as_ref()?;
+		if span.id() != Some(source.id()) {
+			return None;
+		}
+		match self.skipped {
+			Some((last, skipped)) if last == span => return skipped,
+			_ => {},
+		}
+		let mut node = source.find(span);
+		let mut skipped = None;
+		while let Some(current) = node {
+			let skip = self.options.skip_kinds.contains(&current.kind())
+				|| match current.kind() {
+					SyntaxKind::Raw => self.options.skip_code,
+					SyntaxKind::Link => self.options.skip_links,
+					SyntaxKind::FuncCall => self.options.skip_links && is_call(&current, "link"),
+					_ => false,
+				};
+			if skip {
+				skipped = Some(current.span());
+				break;
+			}
+			node = current.parent().cloned();
+		}
+		self.skipped = Some((span, skipped));
+		skipped
+	}
 
-				self.whitespace(t, pos, res);
+	/// The closest syntax node around the span matching the predicate.
+	fn ancestor(&self, span: Span, predicate: impl Fn(&LinkedNode) -> bool) -> Option<Span> {
+		let source = self.source.as_ref()?;
+		if span.id() != Some(source.id()) {
+			return None;
+		}
+		let mut node = source.find(span);
+		while let Some(current) = node {
+			if predicate(&current) {
+				return Some(current.span());
+			}
+			node = current.parent().cloned();
+		}
+		None
+	}
+
+	/// Whether the text at the span is marked to be ignored.
+	fn ignored(&mut self, span: Span) -> bool {
+		let Some(source) = self.source.as_ref() else {
+			return false;
+		};
+		if span.id() != Some(source.id()) {
+			return false;
+		}
+		match self.ignored {
+			Some((last, ignored)) if last == span => return ignored,
+			_ => {},
+		}
+		let mut node = source.find(span);
+		let line = node
+			.as_ref()
+			.and_then(|node| source.byte_to_line(node.offset()));
+		let mut ignored = line.is_some_and(|line| self.ignored_lines.contains(&line));
+		while let Some(current) = node.filter(|_| ignored.not()) {
+			// the entries of the bibliography are not in the language of the document
+			if self.options.bibliography.not() && is_bibliography(&current) {
+				ignored = true;
+				break;
+			}
+			ignored = current
+				.next_sibling()
+				.and_then(|next| {
+					next.cast::<ast::Label>()
+						.map(|label| label.get() == IGNORE_LABEL)
+				})
+				.unwrap_or(false);
+			node = current.parent().cloned();
+		}
+		self.ignored = Some((span, ignored));
+		ignored
+	}
+
+	/// Region of the text at the span, set by the innermost region of the source around it.
+	fn region(&self, span: Span) -> Option<Region> {
+		let offset = self.source.as_ref()?.range(span)?.start;
+		self.regions
+			.iter()
+			.filter(|(range, _)| range.contains(&offset))
+			.max_by_key(|(range, _)| range.start)
+			.and_then(|&(_, region)| region)
+	}
+
+	/// Byte offset of a glyph in its syntax node.
+	fn offset(&self, (span, offset): (Span, u16)) -> u32 {
+		// typst reports offsets past `u16::MAX` as 0, continue after the previous glyph instead
+		if offset == 0 && span == self.span.0 && self.span.1 > u32::from(u16::MAX) {
+			self.span.1
+		} else {
+			u32::from(offset)
+		}
+	}
+
+	fn frame(&mut self, frame: &Frame, pos: Point, file_id: FileId) {
+		if self.options.columns.not() {
+			self.items(frame.items().map(|&(p, ref item)| (p + pos, item)), file_id);
+			return;
+		}
+		for (idx, column) in columns(frame).into_iter().enumerate() {
+			if idx > 0 {
+				self.flow_break = true;
+			}
+			self.items(column.into_iter().map(|(p, item)| (p + pos, item)), file_id);
+		}
+	}
+
+	fn items<'a>(&mut self, items: impl Iterator<Item = (Point, &'a FrameItem)>, file_id: FileId) {
+		let mut items = text_order(items.collect()).into_iter().peekable();
+		while let Some((pos, item)) = items.next() {
+			if self.is_marker(item, items.peek().map(|&(_, item)| item)) {
+				continue;
+			}
+			self.item(pos, item, file_id);
+		}
+	}
+
+	/// Whether the item is the marker of the list item starting with the next item.
+	fn is_marker(&self, item: &FrameItem, next: Option<&FrameItem>) -> bool {
+		let FrameItem::Text(marker) = item else {
+			return false;
+		};
+		let Some(FrameItem::Text(next)) = next else {
+			return false;
+		};
+		marker.glyphs.iter().all(|g| g.span.0.is_detached())
+			&& next
+				.glyphs
+				.iter()
+				.find(|g| g.span.0.is_detached().not())
+				.and_then(|g| self.ancestor(g.span.0, is_list_item))
+				.is_some_and(|node| Some(node) != self.part)
+	}
+
+	fn item(&mut self, pos: Point, item: &FrameItem, file_id: FileId) {
+		use typst::introspection::Meta as M;
+		use FrameItem as I;
+		match item {
+			I::Group(g) => self.frame(&g.frame, pos, file_id),
+			I::Text(t) => {
+				if self.in_math(pos) {
+					return;
+				}
+				let spanned = t.glyphs.iter().find(|g| g.span.0.is_detached().not());
+				if t.glyphs
+					.iter()
+					.filter(|g| g.span.0.is_detached().not())
+					.all(|g| self.ignored(g.span.0))
+					&& spanned.is_some()
+				{
+					self.x = pos.x + t.width();
+					return;
+				}
+				// captions are checked on their own
+				let caption = spanned.map(|g| self.ancestor(g.span.0, is_caption));
+				if let Some(caption) = caption.filter(|caption| *caption != self.caption) {
+					self.seperate();
+					self.caption = caption;
+				}
+				let bibliography = spanned.map(|g| self.ancestor(g.span.0, is_bibliography));
+				if let Some(bibliography) =
+					bibliography.filter(|bibliography| *bibliography != self.bibliography)
+				{
+					self.seperate();
+					self.bibliography = bibliography;
+					self.mapping.bibliography = bibliography.is_some();
+				}
+				// text without a span keeps the region, like the markers of lists
+				let region = spanned.map_or(self.mapping.region, |g| self.region(g.span.0));
+				if self.mapping.language != t.lang || self.mapping.region != region {
+					self.seperate();
+				}
+				self.mapping.language = t.lang;
+				self.mapping.region = region;
+
+				let link = self.in_link(pos);
+				let shifted = self.shifted(t, pos);
+				// lines of the same skipped block share one placeholder
+				let continued = self.skipping.is_some()
+					&& t.glyphs
+						.first()
+						.and_then(|g| self.skipped(g.span.0))
+						.or(link) == self.skipping;
+				// the cell or list item and heading around the text, blank text keeps the heading
+				let (mut part, mut heading) = (self.part, self.heading);
+				if let Some(g) = spanned {
+					part = self.ancestor(g.span.0, is_part);
+					if t.text.trim().is_empty().not() {
+						heading = self.ancestor(g.span.0, is_heading);
+					}
+				}
+				if !continued {
+					self.join(t, pos, shifted, (part, heading), file_id);
+				}
+				self.part = part;
+				self.heading = heading;
 				self.x = pos.x + t.width();
-				self.y = pos.y;
-				self.text += t.text.as_str();
-
-				let mut iter = t.glyphs.iter();
-				for _ in t.text.encode_utf16() {
-					let g = iter.next();
-					let m = g
-						.map(|g| (g.span.0, g.span.1..(g.span.1 + g.range.len() as u16)))
-						.unwrap_or((Span::detached(), 0..0));
-					if let Some(id) = m.0.id() {
-						self.span = (m.0, m.1.end);
-						if id == file_id {
-							self.contains_file = true;
+				// keep the baseline of the line for shifted runs
+				if !shifted {
+					self.y = pos.y;
+					self.size = t.size;
+				}
+				// typst adds a detached hyphen glyph for hyphenated words
+				self.hyphenated = t.text.ends_with(SOFT_HYPHEN)
+					|| t.glyphs
+						.last()
+						.is_some_and(|g| g.span.0.is_detached() && g.range.is_empty());
+
+				// the glyph of each byte, ligatures and clusters are one glyph for several chars
+				let mut glyph_at = vec![None; t.text.len()];
+				for (index, g) in t.glyphs.iter().enumerate() {
+					for at in &mut glyph_at[usize::from(g.range.start)..usize::from(g.range.end)] {
+						at.get_or_insert(index);
+					}
+				}
+				// start of the current glyph in its span
+				let mut glyph_start = None;
+				// spaces around ignored text are collapsed as well
+				let mut whitespace = self.text.ends_with(' ');
+				for (index, c) in t.text.char_indices() {
+					let g = glyph_at[index].map(|i| &t.glyphs[i]);
+					if g.is_some_and(|g| self.ignored(g.span.0)) {
+						whitespace = self.text.ends_with(' ');
+						continue;
+					}
+					// skipped text is replaced by a single placeholder
+					let node = g.and_then(|g| self.skipped(g.span.0)).or(link);
+					if let Some(node) = node {
+						if self.skipping != Some(node) {
+							self.insert_placeholder(node);
+							self.skipping = Some(node);
+						}
+						whitespace = false;
+						continue;
+					}
+					self.skipping = None;
+					let space = g
+						.filter(|_| c.is_whitespace())
+						.and_then(|g| self.source_space(g.span.0));
+					if let Some(space) = space {
+						self.push_space(space, file_id);
+						whitespace = false;
+						continue;
+					}
+					// soft hyphens only matter for the layout
+					let skip = c == SOFT_HYPHEN;
+					// runs of whitespace are collapsed into a single space
+					let collapse = whitespace && c.is_whitespace();
+					whitespace = c.is_whitespace();
+					if collapse {
+						self.text.pop();
+						self.text.push(' ');
+					} else if skip.not() {
+						self.text.push(match c {
+							'“' | '”' | '„' | '‟' if self.options.ascii_quotes => '"',
+							'‘' | '’' | '‚' | '‛' if self.options.ascii_quotes => '\'',
+							'\u{A0}' | '\u{202F}' if self.options.ascii_spaces => ' ',
+							c => c,
+						});
+					}
+					let m = match g.zip(glyph_at[index]) {
+						Some((g, i)) => {
+							let start = match glyph_start {
+								Some((glyph, start)) if glyph == i => start,
+								_ => self.offset(g.span),
+							};
+							glyph_start = Some((i, start));
+							// the chars of a ligature share its glyph
+							let start = start + (index - usize::from(g.range.start)) as u32;
+							(g.span.0, start..(start + c.len_utf8() as u32))
+						},
+						None => (Span::detached(), 0..0),
+					};
+					for _ in 0..c.len_utf16() {
+						if skip {
+							continue;
+						}
+						let m = m.clone();
+						if let Some(id) = m.0.id() {
+							self.span = (m.0, m.1.end);
+							if id == file_id {
+								self.contains_file = true;
+							}
+						}
+						if collapse {
+							let Some(last) = self.mapping.chars.last() else {
+								continue;
+							};
+							if last.0 == m.0 && last.1.end == m.1.start {
+								self.mapping.chars.set_last((m.0, last.1.start..m.1.end));
+							} else if last.0.is_detached() {
+								self.mapping.chars.set_last(m);
+							}
+							continue;
 						}
+						self.mapping.chars.push(m);
+					}
+				}
+				self.pack(file_id);
+				self.split(file_id);
+			},
+			I::Meta(M::Elem(elem), size) => {
+				if let Some(equation) = elem.to_packed::<EquationElem>() {
+					self.equation(equation, pos, *size, file_id);
+				} else if size.x.approx_eq(Abs::zero()).not() {
+					// the generated text of elements like references has no span either
+					if let Some(node) = self.skipped(elem.span()) {
+						self.link = Some((node, pos, pos + size.to_point()));
 					}
-					self.mapping.chars.push(m);
 				}
 			},
-			I::Meta(M::Link(..) | M::Elem(..) | M::Hide, _) | I::Shape(..) | I::Image(..) => {},
+			// the text of urls has no span, so it is skipped by its area
+			I::Meta(M::Link(Destination::Url(_)), size) if self.options.skip_links => {
+				self.link = Some((Span::detached(), pos, pos + size.to_point()));
+			},
+			I::Image(image, _, span) if self.options.alt_text => {
+				if let Some(alt) = image.alt() {
+					self.alt_text(alt, *span, file_id);
+				}
+			},
+			// no option to check hidden content: typst 0.11 already strips the text of hidden
+			// frames in the layout, see the `show hide` rule in the readme instead
+			I::Meta(M::Hide, _) => {},
+			I::Meta(M::Link(..), _) | I::Shape(..) | I::Image(..) => {},
+		}
+	}
+}
+
+/// The name of the called function, like `table` or `table.cell`.
+fn callee(node: &LinkedNode) -> Option<String> {
+	match node.cast::<ast::FuncCall>()?.callee() {
+		ast::Expr::Ident(ident) => Some(ident.as_str().into()),
+		ast::Expr::FieldAccess(access) => match access.target() {
+			ast::Expr::Ident(ident) => {
+				Some(format!("{}.{}", ident.as_str(), access.field().as_str()))
+			},
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+/// Whether the node calls the function `name`.
+fn is_call(node: &LinkedNode, name: &str) -> bool {
+	callee(node).is_some_and(|callee| callee == name)
+}
+
+/// Whether the node is a positional argument of a table or grid, like a cell.
+fn is_cell(node: &LinkedNode) -> bool {
+	node.kind() != SyntaxKind::Named
+		&& node.parent_kind() == Some(SyntaxKind::Args)
+		&& node
+			.parent()
+			.and_then(LinkedNode::parent)
+			.and_then(callee)
+			.is_some_and(|callee| {
+				matches!(
+					callee.as_str(),
+					"table"
+						| "grid" | "table.header"
+						| "grid.header" | "table.footer"
+						| "grid.footer"
+				)
+			})
+}
+
+/// Whether the node is an item of a bullet, numbered or term list.
+fn is_list_item(node: &LinkedNode) -> bool {
+	matches!(
+		node.kind(),
+		SyntaxKind::ListItem | SyntaxKind::EnumItem | SyntaxKind::TermItem
+	)
+}
+
+/// Whether the node is a table cell or list item.
+fn is_part(node: &LinkedNode) -> bool {
+	is_cell(node) || is_list_item(node)
+}
+
+/// Whether the node is a heading.
+fn is_heading(node: &LinkedNode) -> bool {
+	node.kind() == SyntaxKind::Heading || is_call(node, "heading")
+}
+
+/// Whether the node is the `bibliography` call, which all text of the bibliography maps to.
+fn is_bibliography(node: &LinkedNode) -> bool {
+	is_call(node, "bibliography")
+}
+
+/// Whether the node is the `caption` argument of a figure.
+fn is_caption(node: &LinkedNode) -> bool {
+	node.cast::<ast::Named>()
+		.is_some_and(|named| named.name().as_str() == "caption")
+		&& node
+			.parent()
+			.and_then(LinkedNode::parent)
+			.is_some_and(|call| is_call(call, "figure"))
+}
+
+// scripts written without spaces between words
+fn is_ideographic(c: char) -> bool {
+	matches!(c,
+		'\u{3040}'..='\u{30FF}'
+		| '\u{3400}'..='\u{4DBF}'
+		| '\u{4E00}'..='\u{9FFF}'
+		| '\u{F900}'..='\u{FAFF}'
+		| '\u{20000}'..='\u{2FA1F}')
+}
+
+fn word_count(text: &str) -> usize {
+	text.split_whitespace()
+		.map(|word| {
+			let ideographs = word.chars().filter(|&c| is_ideographic(c)).count();
+			let rest = word
+				.split(is_ideographic)
+				.any(|s| s.chars().any(char::is_alphanumeric));
+			ideographs + usize::from(rest)
+		})
+		.sum()
+}
+
+/// Whether the positions differ by at most the tolerance.
+fn close(a: Abs, b: Abs, tolerance: Abs) -> bool {
+	(a - b).abs() <= tolerance || a.approx_eq(b)
+}
+
+/// Lines following a comment to ignore the next line.
+fn ignored_lines(source: &Source) -> HashSet<usize> {
+	let mut lines = HashSet::new();
+	let mut nodes = vec![LinkedNode::new(source.root())];
+	while let Some(node) = nodes.pop() {
+		if node.kind() == SyntaxKind::LineComment
+			&& node.text().trim_start_matches('/').trim() == IGNORE_NEXT_LINE
+		{
+			if let Some(line) = source.byte_to_line(node.offset()) {
+				lines.insert(line + 1);
+			}
+		}
+		nodes.extend(node.children());
+	}
+	lines
+}
+
+/// Reorders the text items of right-to-left lines, laid out from left to right, into the order
+/// of their text, runs of left-to-right text within keep their order.
+///
+/// The items are moved along the order of the text, so adjacent items stay adjacent and the converter can join them like the items of a left-to-right line.
+fn text_order(items: Vec<(Point, &FrameItem)>) -> Vec<(Point, &FrameItem)> {
+	let mut res = Vec::with_capacity(items.len());
+	let mut items = items.into_iter().peekable();
+	while let Some((pos, item)) = items.next() {
+		let FrameItem::Text(first) = item else {
+			res.push((pos, item));
+			continue;
+		};
+		let mut line = vec![(pos, first, item)];
+		while first.lang.dir() == Dir::RTL {
+			let Some(&(next, FrameItem::Text(t))) = items.peek() else {
+				break;
+			};
+			if (next.y - pos.y).abs() >= first.size / 2.0 {
+				break;
+			}
+			line.push((next, t, items.next().unwrap().1));
+		}
+		if line.len() == 1 {
+			res.push((pos, item));
+			continue;
+		}
+		let right = line
+			.iter()
+			.map(|(pos, t, _)| pos.x + t.width())
+			.fold(Abs::zero(), Abs::max);
+		// runs of left-to-right text keep their order, the runs and other items are reversed
+		let mut runs = Vec::<Vec<(Point, &TextItem, &FrameItem)>>::new();
+		for (pos, t, item) in line {
+			match runs.last_mut() {
+				Some(run) if ltr(t) && ltr(run[0].1) => run.push((pos, t, item)),
+				_ => runs.push(vec![(pos, t, item)]),
+			}
+		}
+		// mirrored, so the start of the line is far from the end of the previous one
+		let mut end = -right;
+		let mut prev_left = None;
+		for run in runs.into_iter().rev() {
+			let right = run
+				.last()
+				.map_or(Abs::zero(), |(pos, t, _)| pos.x + t.width());
+			// the run follows to the left of the previous one
+			let mut gap = prev_left.map_or(Abs::zero(), |left: Abs| (left - right).abs());
+			let mut prev_right = None;
+			for &(pos, t, item) in &run {
+				if let Some(prev_right) = prev_right {
+					gap = (pos.x - prev_right).abs();
+				}
+				res.push((Point::new(end + gap, pos.y), item));
+				end += gap + t.width();
+				prev_right = Some(pos.x + t.width());
+			}
+			prev_left = run.first().map(|(pos, _, _)| pos.x);
+		}
+	}
+	res
+}
+
+/// Whether the glyphs of the text are laid out in the order of the text, single glyphs count
+/// as right-to-left.
+fn ltr(text: &TextItem) -> bool {
+	match (text.glyphs.first(), text.glyphs.last()) {
+		(Some(first), Some(last)) => first.range.start < last.range.start,
+		_ => false,
+	}
+}
+
+/// Byte ranges of the source with the region set by `#set text(region: ..)` until the end of
+/// the surrounding block, or by `text(region: ..)[..]` for its arguments.
+fn regions(source: &Source) -> Vec<(Range<usize>, Option<Region>)> {
+	let mut regions = Vec::new();
+	let mut nodes = vec![LinkedNode::new(source.root())];
+	while let Some(node) = nodes.pop() {
+		let args = if let Some(set) = node.cast::<ast::SetRule>() {
+			let scope = node.parent().map_or(node.range(), |parent| parent.range());
+			let text = matches!(set.target(), ast::Expr::Ident(ident) if ident.as_str() == "text");
+			text.then(|| (set.args(), node.range().end..scope.end))
+		} else if is_call(&node, "text") {
+			node.cast::<ast::FuncCall>()
+				.map(|call| (call.args(), node.range()))
+		} else {
+			None
+		};
+		let region = args.and_then(|(args, range)| {
+			let region = args.items().find_map(|arg| match arg {
+				ast::Arg::Named(named) if named.name().as_str() == "region" => Some(named.expr()),
+				_ => None,
+			})?;
+			match region {
+				ast::Expr::Str(region) => Some((range, region.get().parse().ok())),
+				ast::Expr::None(_) => Some((range, None)),
+				_ => None,
+			}
+		});
+		regions.extend(region);
+		nodes.extend(node.children());
+	}
+	regions
+}
+
+/// The largest char boundary in the text not after the index.
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+	if index >= text.len() {
+		return text.len();
+	}
+	(0..=index)
+		.rev()
+		.find(|&i| text.is_char_boundary(i))
+		.unwrap_or(0)
+}
+
+/// Group the items of a frame into columns by their horizontal extent, from left to right.
+fn columns(frame: &Frame) -> Vec<Vec<(Point, &FrameItem)>> {
+	let mut columns = Vec::<(Abs, Abs, Vec<(usize, Point, &FrameItem)>)>::new();
+	for (idx, &(p, ref item)) in frame.items().enumerate() {
+		let width = match item {
+			FrameItem::Group(g) => g.frame.width(),
+			FrameItem::Text(t) => t.width(),
+			FrameItem::Shape(..) | FrameItem::Image(..) | FrameItem::Meta(..) => Abs::zero(),
+		};
+		let (mut start, mut end) = (p.x, p.x + width);
+		let mut items = vec![(idx, p, item)];
+		// merge all columns overlapping with the item
+		while let Some(other) = columns.iter().position(|&(s, e, _)| start <= e && s <= end) {
+			let (s, e, other) = columns.swap_remove(other);
+			start = start.min(s);
+			end = end.max(e);
+			items.extend(other);
+		}
+		columns.push((start, end, items));
+	}
+	columns.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+	columns
+		.into_iter()
+		.map(|(_, _, mut items)| {
+			items.sort_by_key(|&(idx, _, _)| idx);
+			items.into_iter().map(|(_, p, item)| (p, item)).collect()
+		})
+		.collect()
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+	use std::path::PathBuf;
+
+	use typst::{
+		foundations::Bytes,
+		syntax::LinkedNode,
+		text::Font,
+		visualize::{Color, Paint},
+		World,
+	};
+
+	use super::*;
+
+	/// A world with the text as its main file.
+	pub(crate) fn world(text: &str) -> lt_world::LtWorld {
+		let main = PathBuf::from("/typst-languagetool/main.typ");
+		let mut world = lt_world::LtWorld::new(main.clone(), None);
+		world.use_shadow_file(&main, text.into());
+		world
+	}
+
+	pub(crate) fn compile(text: &str) -> (Document, Source) {
+		let world = world(text);
+		let doc = world.compile().unwrap();
+		(doc, world.main())
+	}
+
+	/// A suggestion of the range in UTF-16 code units, without a message or rule.
+	pub(crate) fn suggestion(start: usize, end: usize) -> Suggestion {
+		Suggestion {
+			start,
+			end,
+			message: String::new(),
+			short_message: String::new(),
+			replacements: Vec::new(),
+			rule_description: String::new(),
+			rule_id: String::new(),
+			category: String::new(),
+		}
+	}
+
+	/// The source text of every location of a suggestion of `len` units from `start`.
+	pub(crate) fn located<'a>(
+		mapping: &Mapping,
+		source: &'a Source,
+		start: usize,
+		len: usize,
+	) -> Vec<&'a str> {
+		mapping
+			.location(&suggestion(start, start + len), source)
+			.into_iter()
+			.map(|range| &source.text()[range])
+			.collect()
+	}
+
+	fn text_item(text: &str, glyphs: Vec<typst::text::Glyph>) -> TextItem {
+		let data = typst_assets::fonts().next().unwrap();
+		let font = Font::new(Bytes::from_static(data), 0).unwrap();
+		TextItem {
+			font,
+			size: Abs::pt(10.0),
+			fill: Paint::Solid(Color::BLACK),
+			stroke: None,
+			lang: Lang::ENGLISH,
+			text: text.into(),
+			glyphs,
+		}
+	}
+
+	#[test]
+	fn test_empty_glyphs() {
+		let item = text_item("\u{200D}", Vec::new());
+		let line_spacing = (item.font.metrics().cap_height + LINE_SPACING).at(item.size);
+		let mut converter = Converter::new(None);
+		let file_id = FileId::new(None, typst::syntax::VirtualPath::new("main.typ"));
+		let pos = Point::new(Abs::pt(20.0), line_spacing);
+		converter.item(pos, &FrameItem::Text(item), file_id);
+		assert_eq!(converter.text, "\u{200D}");
+	}
+
+	#[test]
+	fn test_collapse_whitespace() {
+		let glyphs = (0..4)
+			.map(|i| typst::text::Glyph {
+				id: 0,
+				x_advance: Em::new(0.5),
+				x_offset: Em::zero(),
+				range: i..i + 1,
+				span: (Span::detached(), i),
+			})
+			.collect();
+		let item = text_item("a  b", glyphs);
+		let mut converter = Converter::new(None);
+		let file_id = FileId::new(None, typst::syntax::VirtualPath::new("main.typ"));
+		converter.item(Point::zero(), &FrameItem::Text(item), file_id);
+		assert_eq!(converter.text, "a b");
+		let ranges = converter
+			.mapping
+			.chars
+			.iter()
+			.map(|(_, range)| range)
+			.collect::<Vec<_>>();
+		assert_eq!(ranges, [0..1, 1..3, 3..4]);
+	}
+
+	#[test]
+	fn test_long_text_node() {
+		let text = format!(
+			"#set page(height: auto)\n{}tpyo",
+			"Some words in a very long paragraph ".repeat(2000)
+		);
+		let (doc, source) = compile(&text);
+		let chunks = document(&doc, usize::MAX, &source, &ConvertOptions::default()).unwrap();
+		let (text, mapping) = chunks.last().unwrap();
+		assert!(text.contains("paragraphSome").not());
+		let start = text.rfind("tpyo").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 4), &source);
+		let end = source.text().len();
+		assert_eq!(locations, vec![(end - 4)..end]);
+	}
+
+	#[test]
+	fn test_heading_parbreak() {
+		let (doc, source) = compile(
+			"#show heading: set block(below: 7.15pt)\n= Introduction\nThis chapter is short.",
+		);
+		let chunks = document(&doc, 1000, &source, &ConvertOptions::default()).unwrap();
+		assert_eq!(chunks.len(), 1);
+		assert!(chunks[0]
+			.0
+			.ends_with("Introduction\n\nThis chapter is short."));
+	}
+
+	#[test]
+	fn test_columns() {
+		let (doc, source) = compile(
+			"#set page(width: 300pt, height: 100pt, margin: 10pt)\n#columns(2)[\n\
+			The first column holds a sentence that wraps across several lines and \
+			continues into the second column without any break. The sentence in the second \
+			column ends here.]",
+		);
+		let options = ConvertOptions { columns: true, ..Default::default() };
+		let chunks = document(&doc, 1000, &source, &options).unwrap();
+		assert_eq!(chunks.len(), 1);
+		let text = chunks[0].0.trim_start();
+		assert!(text.contains('\n').not());
+		assert!(text.ends_with("continues into the second column without any break. The sentence in the second column ends here."));
+	}
+
+	#[test]
+	fn test_hyphenation() {
+		let (doc, source) = compile(
+			"#set page(width: 120pt, margin: 10pt)\n\
+			Extraordinary circum-?stances necessi-?tate com-?pre-?hen-?sive documentation.",
+		);
+		let chunks = document(&doc, 1000, &source, &ConvertOptions::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		assert!(
+			text.ends_with("Extraordinary circumstances necessitate comprehensive documentation.")
+		);
+		let start = text.find("comprehensive").unwrap();
+		assert_eq!(
+			located(mapping, &source, start, 13),
+			["com", "pre", "hen", "sive"]
+		);
+	}
+
+	#[test]
+	fn test_text_offsets() {
+		let (doc, source) = compile("Some *strong* text with a typo.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		let start = text.find("strong").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 6), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "strong");
+		assert_eq!(
+			mapping.text_offsets(locations[0].clone(), &source),
+			vec![start..start + 6]
+		);
+	}
+
+	#[test]
+	fn test_leading() {
+		let text = format!(
+			"#set par(leading: 1.2em)\n{}",
+			"Some words in a long paragraph. ".repeat(20)
+		);
+		let (doc, source) = compile(&text);
+		let text = |options| document(&doc, 1000, &source, &options).unwrap().remove(0).0;
+		assert!(text(ConvertOptions::default()).trim().contains("\n\n"));
+		let options = ConvertOptions {
+			leading: Em::new(1.2),
+			..Default::default()
+		};
+		assert_eq!(
+			text(options).trim(),
+			"Some words in a long paragraph. ".repeat(20).trim()
+		);
+	}
+
+	#[test]
+	fn test_shifted_text() {
+		let (doc, source) = compile(
+			"A word#footnote[A note.] \\\n in the middle of a#super[b] \\\n sentence H#sub[2]O.",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(
+			chunks[0].0.trim(),
+			"A word¹ in the middle of ab sentence H₂O.\n\n¹\n\nA note."
+		);
+	}
+
+	#[test]
+	fn test_options_builder() {
+		let (doc, source) = compile("Some `code` and a _word_.\n\nThe next paragraph.");
+		let built = ConvertOptions::default()
+			.with_skip_code(false)
+			.with_overlap(5)
+			.with_math_placeholder("X")
+			.with_skip_kinds([SyntaxKind::Emph]);
+		let options = ConvertOptions {
+			skip_code: false,
+			overlap: 5,
+			math_placeholder: "X".into(),
+			skip_kinds: HashSet::from([SyntaxKind::Emph]),
+			..Default::default()
+		};
+		let chunks = document(&doc, 1, &source, &built).unwrap();
+		assert_eq!(chunks, document(&doc, 1, &source, &options).unwrap());
+		assert_eq!(chunks[0].0.trim(), "Some code and a X.");
+
+		let (doc, source) = compile("Plain text.\n\nThe next paragraph.");
+		assert_eq!(
+			document_file(&doc, 1, source.id()),
+			document(&doc, 1, &source, &ConvertOptions::default()).unwrap()
+		);
+	}
+
+	#[test]
+	fn test_document_text() {
+		let (doc, source) = compile(
+			"#set page(header: [Header])\nFirst paragraph $x$ with `code`.\n\n- Second \
+			 paragraph.\n\nThird paragraph#footnote[A note.] that is long enough to be split. \
+			 #pagebreak() On the next page.",
+		);
+		for options in [
+			ConvertOptions { overlap: 10, ..Default::default() },
+			ConvertOptions { max_chunk_size: 30, ..Default::default() },
+			ConvertOptions {
+				merge_pages: true,
+				pack_paragraphs: true,
+				max_chunk_size: 60,
+				..Default::default()
+			},
+		] {
+			let chunks = document(&doc, 1, &source, &options).unwrap();
+			let text = document_text(&doc, 1, &source, &options).unwrap();
+			assert!(text.len() > 1);
+			assert_eq!(
+				text,
+				chunks.into_iter().map(|(text, _)| text).collect::<Vec<_>>()
+			);
+		}
+
+		// the chars only count the code units without mapping them
+		let mut converter = Converter::new(Some(1))
+			.with_source(source.clone())
+			.text_only();
+		converter.push_frame(&doc.pages[0].frame, Point::zero(), source.id());
+		for (text, mapping) in converter.finish() {
+			assert!(mapping.chars.is_text_only());
+			assert_eq!(mapping.chars.len(), text.encode_utf16().count());
+		}
+	}
+
+	#[test]
+	fn test_document_range() {
+		let (doc, source) = compile("First paragraph.\n\nSecond paragraph.\n\nThird paragraph.");
+		let start = source.text().find("Second").unwrap();
+		let chunks =
+			document_range(&doc, 1, &source, start..start + 6, &Default::default()).unwrap();
+		assert_eq!(chunks.len(), 1);
+		assert_eq!(chunks[0].0.trim(), "Second paragraph.");
+	}
+
+	#[test]
+	fn test_chunk_hash() {
+		let text = "First paragraph.\n\nSecond paragraph.";
+		let options = ConvertOptions { hash_chunks: true, ..Default::default() };
+		let hashes = |text: &str| {
+			let (doc, source) = compile(text);
+			document(&doc, 1, &source, &options)
+				.unwrap()
+				.into_iter()
+				.map(|(_, mapping)| mapping.hash().unwrap())
+				.collect::<Vec<_>>()
+		};
+		let first = hashes(text);
+		assert_eq!(first.len(), 2);
+		assert_eq!(hashes(text), first);
+		let changed = hashes("First paragraph.\n\nSecond sentence.");
+		assert_eq!(changed[0], first[0]);
+		assert_ne!(changed[1], first[1]);
+		assert_eq!(chunk_hash("", Lang::ENGLISH), 0xc2ef_df18_f053_12de_u64);
+
+		let (doc, source) = compile(text);
+		let chunks = document(&doc, 1, &source, &Default::default()).unwrap();
+		assert_eq!(chunks[0].1.hash(), None);
+	}
+
+	#[test]
+	fn test_region() {
+		let (doc, source) = compile(
+			"#set text(lang: \"en\", region: \"gb\")\nColour.\n\n#text(region: \"us\")[Color.]\n\n\
+			 #block[#set text(region: \"au\")\nColour.]\n\nColour again.\n\n#set text(lang: \"de\", \
+			 region: \"at\")\nJänner.\n\n#set text(region: \"gb\")\nJanuar.",
+		);
+		let languages = document(&doc, 1000, &source, &Default::default())
+			.unwrap()
+			.into_iter()
+			.map(|(text, mapping)| (text.trim().to_owned(), mapping.long_language()))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			languages,
+			[
+				("Colour.".into(), "en-GB".into()),
+				("Color.".into(), "en-US".into()),
+				("Colour.".into(), "en-AU".into()),
+				("Colour again.".into(), "en-GB".into()),
+				("Jänner.".into(), "de-AT".into()),
+				// LanguageTool has no rules for the region
+				("Januar.".into(), "de-DE".into()),
+			] as [(String, String); 6]
+		);
+	}
+
+	#[test]
+	fn test_right_to_left() {
+		let (doc, source) = compile(
+			"#set page(width: 6cm)\n#set text(lang: \"he\")\nשלום *עולם* גדול. זהו משפט עם \
+			 _הדגשה_ ועוד מילים רבות על פני כמה שורות.\n\nמילה English *bold* words מילה.",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		assert_eq!(
+			text.trim(),
+			"שלום עולם גדול. זהו משפט עם הדגשה ועוד מילים רבות על פני כמה שורות.\n\nמילה \
+			 English bold words מילה."
+		);
+		let start = crate::utf_16_index(text, text.find("עולם").unwrap());
+		let locations = mapping.location(&suggestion(start, start + 4), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "עולם");
+	}
+
+	#[test]
+	fn test_merge_pages() {
+		let (doc, source) =
+			compile("This sentence continues #pagebreak() on the next page.\n\nAnother one.");
+		let options = ConvertOptions { merge_pages: true, ..Default::default() };
+		let chunks = document(&doc, 1000, &source, &options).unwrap();
+		assert_eq!(chunks.len(), 1);
+		let (text, mapping) = &chunks[0];
+		assert_eq!(
+			text.trim(),
+			"This sentence continues on the next page.\n\nAnother one."
+		);
+		let start = text.find("next").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 4), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "next");
+
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks.len(), 2);
+	}
+
+	#[test]
+	fn test_pages() {
+		let (doc, source) = compile("One. #pagebreak() Two. #pagebreak() Three.");
+		let texts = |pages, merge_pages| {
+			let options = ConvertOptions { pages, merge_pages, ..Default::default() };
+			document(&doc, 1000, &source, &options)
+				.unwrap()
+				.into_iter()
+				.map(|(text, _)| text.trim().to_string())
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(texts(None, false), ["One.", "Two.", "Three."]);
+		assert_eq!(texts(Some(1..3), false), ["Two.", "Three."]);
+		assert_eq!(texts(Some(0..1), true), ["One."]);
+		assert_eq!(texts(Some(2..10), false), ["Three."]);
+		assert!(texts(Some(5..10), false).is_empty());
+		let options = ConvertOptions {
+			pages: Some(Range { start: 2, end: 1 }),
+			..Default::default()
+		};
+		assert!(matches!(
+			document(&doc, 1000, &source, &options),
+			Err(Error::Conversion(_))
+		));
+	}
+
+	#[test]
+	fn test_dedup_suggestions() {
+		let (doc, source) =
+			compile("#set page(header: [Teh title])\nOne #pagebreak() Two #pagebreak() Three");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks.len(), 3);
+		let suggestions = chunks
+			.iter()
+			.map(|(text, mapping)| {
+				let start = text.find("Teh").unwrap();
+				(suggestion(start, start + 3), mapping)
+			})
+			.collect::<Vec<_>>();
+		let suggestions = dedup_suggestions(suggestions, &source);
+		assert_eq!(suggestions.len(), 1);
+	}
+
+	#[test]
+	fn test_headings() {
+		let (doc, source) = compile(
+			"#show heading: set text(size: 11pt)\n\
+			#show heading: set block(below: 0.65em)\n\
+			= Introduction\nA chapter begins here.\n\n\
+			#show heading: it => [*#it.body* ]\n\
+			#heading[Inline]\nSome body.",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(
+			chunks[0].0.trim(),
+			"Introduction\n\nA chapter begins here.\n\nInline\n\nSome body."
+		);
+	}
+
+	#[test]
+	fn test_chunk_info() {
+		let info = |text: &str, options: &ConvertOptions| {
+			let (doc, source) = compile(text);
+			document(&doc, 1, &source, options)
+				.unwrap()
+				.into_iter()
+				.map(|(text, mapping)| {
+					let info = mapping.info();
+					(
+						text.trim().to_owned(),
+						info.starts_mid_sentence,
+						info.ends_mid_sentence,
+					)
+				})
+				.collect::<Vec<_>>()
+		};
+		let options = ConvertOptions::default();
+		assert_eq!(
+			info("Some paragraph.\n\nAnother paragraph.", &options),
+			vec![
+				("Some paragraph.".into(), false, false),
+				("Another paragraph.".into(), false, false),
+			]
+		);
+		assert_eq!(
+			info(
+				"Here a sentence continues #pagebreak() on the next page.\n\nAnother one.",
+				&options
+			),
+			vec![
+				("Here a sentence continues".into(), false, true),
+				("on the next page.".into(), true, false),
+				("Another one.".into(), false, false),
+			]
+		);
+
+		let options = ConvertOptions { max_chunk_size: 30, ..Default::default() };
+		let chunks = info(
+			"A sentence. Another sentence split by the chunk size.",
+			&options,
+		);
+		assert_eq!(
+			chunks,
+			vec![
+				("A sentence. Another".into(), false, true),
+				("Another sentence split by the".into(), true, true),
+				("sentence split by the chunk".into(), true, true),
+				("split by the chunk size.".into(), true, false),
+			]
+		);
+	}
+
+	#[test]
+	fn test_reused_buffers() {
+		let (doc, source) = compile("A long paragraph with some more words.\n\nShort one.");
+		let mut converter = Converter::new(Some(1)).with_source(source.clone());
+		converter.push_frame(&doc.pages[0].frame, Point::zero(), source.id());
+		let chunks = converter.finish();
+		// the buffers of the shorter second chunk started with the capacity of the first one
+		assert!(chunks[1].0.capacity() >= chunks[0].0.capacity());
+		assert!(chunks[1].1.chars.capacity() >= chunks[0].1.chars.capacity());
+		let texts = chunks
+			.iter()
+			.map(|(text, _)| text.trim())
+			.collect::<Vec<_>>();
+		assert_eq!(
+			texts,
+			["A long paragraph with some more words.", "Short one."]
+		);
+		for (text, mapping) in &chunks {
+			assert_eq!(mapping.chars.len(), text.encode_utf16().count());
+		}
+		assert_eq!(
+			chunks,
+			document(&doc, 1, &source, &ConvertOptions::default()).unwrap()
+		);
+	}
+
+	#[test]
+	fn test_compact_chars() {
+		let text = "Some words in a *long* paragraph, with `code` and _emphasis_. ".repeat(100);
+		let (doc, source) = compile(&format!("#set page(height: auto)\n{text}"));
+		let chunks = document(&doc, usize::MAX, &source, &Default::default()).unwrap();
+		let units = chunks
+			.iter()
+			.map(|(_, mapping)| mapping.chars.len())
+			.sum::<usize>();
+		let compact = chunks
+			.iter()
+			.map(|(_, mapping)| mapping.chars.heap_size())
+			.sum::<usize>();
+		let plain = units * std::mem::size_of::<(Span, Range<u32>)>();
+		assert!(compact * 2 < plain, "{compact} of {plain} bytes");
+
+		for (text, mapping) in &chunks {
+			for (index, _) in text.match_indices("words") {
+				let start = crate::utf_16_index(text, index);
+				assert_eq!(located(mapping, &source, start, 5), ["words"]);
+			}
+		}
+	}
+
+	#[test]
+	fn test_ligatures() {
+		let (doc, source) = compile("The first waffle fits.");
+		fn glyphs(frame: &Frame) -> (usize, usize) {
+			frame
+				.items()
+				.map(|(_, item)| match item {
+					FrameItem::Group(group) => glyphs(&group.frame),
+					FrameItem::Text(text) => (text.text.chars().count(), text.glyphs.len()),
+					_ => (0, 0),
+				})
+				.fold((0, 0), |(chars, glyphs), item| {
+					(chars + item.0, glyphs + item.1)
+				})
+		}
+		let glyphs = glyphs(&doc.pages[0].frame);
+		// the font substitutes ligatures, so there are fewer glyphs than chars
+		assert!(glyphs.1 < glyphs.0);
+
+		let (text, mapping) = document(&doc, 1000, &source, &Default::default())
+			.unwrap()
+			.remove(0);
+		for word in ["The", "first", "waffle", "fits", "irst", "le"] {
+			let index = text.find(word).unwrap();
+			let start = crate::utf_16_index(&text, index);
+			assert_eq!(located(&mapping, &source, start, word.len()), [word]);
+		}
+	}
+
+	#[test]
+	fn test_linebreak_sentences() {
+		let (doc, source) =
+			compile("It rains \\\nbirds sing. \\\nA sun shines #linebreak() over us");
+		let text = |options| document(&doc, 1000, &source, &options).unwrap().remove(0).0;
+		assert_eq!(
+			text(ConvertOptions::default()).trim(),
+			"It rains\nbirds sing.\nA sun shines\nover us"
+		);
+
+		let options = ConvertOptions {
+			linebreak_sentences: true,
+			..Default::default()
+		};
+		assert_eq!(
+			text(options.clone()).trim(),
+			"It rains\n\nbirds sing.\nA sun shines\n\nover us"
+		);
+		// the lines stay in the chunk of their paragraph
+		assert_eq!(document(&doc, 1, &source, &options).unwrap().len(), 1);
+	}
+
+	#[test]
+	fn test_hard_linebreaks() {
+		let (doc, source) = compile(
+			"#set page(width: 6cm)\nA first line that is long enough to wrap \\\nA second line \\\nThe \
+			 end",
+		);
+		let chunks = document(&doc, 1000, &source, &ConvertOptions::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		assert_eq!(
+			text.trim(),
+			"A first line that is long enough to wrap\nA second line\nThe end"
+		);
+		let start = crate::utf_16_index(text, text.find("wrap\n").unwrap() + 4);
+		let locations = mapping.location(&suggestion(start, start + 1), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "\\");
+	}
+
+	#[test]
+	fn test_ascii_spaces() {
+		let (doc, source) = compile("See page~12 and 10#sym.space.nobreak.narrow%.");
+		let text = |options| document(&doc, 1000, &source, &options).unwrap().remove(0);
+		let (nbsp, _) = text(ConvertOptions::default());
+		assert_eq!(nbsp.trim(), "See page\u{A0}12 and 10\u{202F}%.");
+
+		let options = ConvertOptions { ascii_spaces: true, ..Default::default() };
+		let (text, mapping) = text(options);
+		assert_eq!(text.trim(), "See page 12 and 10 %.");
+		let start = text.find(" 12").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 1), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "~");
+	}
+
+	#[test]
+	fn test_ascii_quotes() {
+		let (doc, source) = compile("She said \"it's good\" today.");
+		let options = ConvertOptions { ascii_quotes: true, ..Default::default() };
+		let chunks = document(&doc, 1000, &source, &options).unwrap();
+		let (text, mapping) = &chunks[0];
+		assert_eq!(text.trim(), "She said \"it's good\" today.");
+		let start = text.find('\'').unwrap();
+		let locations = mapping.location(&suggestion(start - 2, start + 2), &source);
+		assert_eq!(
+			&source.text()[locations[0].start..locations.last().unwrap().end],
+			"it's"
+		);
+		let start = text.rfind('"').unwrap();
+		let end = source.text().rfind('"').unwrap();
+		let locations = mapping.location(&suggestion(start, start + 1), &source);
+		assert_eq!(locations, vec![end..end + 1]);
+
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks[0].0.trim(), "She said “it’s good” today.");
+	}
+
+	#[test]
+	fn test_emoji_offset() {
+		let (doc, source) = compile("Smile 😀 Teh word.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		let start = crate::utf_16_index(text, text.find("Teh").unwrap());
+		let suggestion = suggestion(start, start + 3);
+		assert_eq!(&text[suggestion.utf_8_range(text)], "Teh");
+		let locations = mapping.location(&suggestion, &source);
+		assert_eq!(&source.text()[locations[0].clone()], "Teh");
+	}
+
+	#[test]
+	fn test_split_long_paragraph() {
+		let text = format!(
+			"#set page(height: auto)\n{}Teh end.",
+			"Some words in a long paragraph. ".repeat(1000)
+		);
+		let (doc, source) = compile(&text);
+		let chunks = document(&doc, usize::MAX, &source, &Default::default()).unwrap();
+		assert!(chunks.len() > 1);
+		for (text, mapping) in &chunks {
+			assert!(text.len() <= MAX_CHUNK_SIZE);
+			assert_eq!(text.encode_utf16().count(), mapping.chars.len());
+			assert!(text.trim_start().starts_with("Some words"));
+		}
+		let (text, mapping) = chunks.last().unwrap();
+		let start = text.find("Teh").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 3), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "Teh");
+	}
+
+	#[test]
+	fn test_overlap() {
+		let (doc, source) = compile("The first paragraph has Teh typo.\n\nThe second one.");
+		let options = ConvertOptions { overlap: 20, ..Default::default() };
+		let chunks = document(&doc, 1, &source, &options).unwrap();
+		assert_eq!(chunks.len(), 2);
+		assert_eq!(chunks[1].0, "has Teh typo.\n\nThe second one.");
+		let checked = chunks
+			.iter()
+			.filter(|(text, mapping)| {
+				let start = text.find("Teh").unwrap();
+				mapping.is_context(&suggestion(start, start + 3)).not()
+			})
+			.count();
+		assert_eq!(checked, 1);
+	}
+
+	#[cfg(feature = "rayon")]
+	#[test]
+	fn test_parallel_pages() {
+		let (doc, source) =
+			compile("#set page(header: [Header])\nOne #pagebreak() Two #pagebreak() Three");
+		let options = ConvertOptions::default();
+		assert_eq!(
+			pages(&doc, 1000, &source, &options, false),
+			pages_parallel(&doc, 1000, &source, &options, false)
+		);
+	}
+
+	#[test]
+	fn test_apply_suggestion() {
+		let (doc, source) = compile("Here is Teh *bold* word.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		let start = text.find("Teh").unwrap();
+		let (range, replacement) =
+			apply_suggestion(&suggestion(start, start + 3), "The", mapping, &source).unwrap();
+		let mut edited = source.text().to_owned();
+		edited.replace_range(range, &replacement);
+		assert_eq!(edited, "Here is The *bold* word.");
+
+		let end = text.find("word").unwrap();
+		assert_eq!(
+			apply_suggestion(&suggestion(start, end), "The bold", mapping, &source),
+			Err(ApplyError::Disjoint)
+		);
+		assert_eq!(
+			apply_suggestion(&suggestion(0, 1), "", mapping, &source),
+			Err(ApplyError::NoLocation)
+		);
+	}
+
+	#[test]
+	fn test_narrow_location() {
+		let source = Source::detached("Some *bold words* here.");
+		let root = LinkedNode::new(source.root());
+		let strong = root
+			.children()
+			.find(|node| node.kind() == SyntaxKind::Strong)
+			.unwrap();
+		let mapping = Mapping {
+			chars: (1..5).map(|i| (strong.span(), i..i + 1)).collect(),
+			..Mapping::new(Lang::ENGLISH)
+		};
+		assert_eq!(located(&mapping, &source, 0, 4), ["bold"]);
+	}
+
+	#[test]
+	fn test_location_resolution() {
+		let source = Source::detached("Some text here.");
+		let root = LinkedNode::new(source.root());
+		let text = root.children().next().unwrap();
+		// "Some" and "text" joined by an inserted space
+		let mapping = Mapping {
+			chars: (0..4)
+				.map(|i| (text.span(), i..i + 1))
+				.chain([(Span::detached(), 0..0)])
+				.chain((5..9).map(|i| (text.span(), i..i + 1)))
+				.collect(),
+			..Mapping::new(Lang::ENGLISH)
+		};
+		let resolution = |start, end| mapping.location_resolution(&suggestion(start, end), &source);
+		let (locations, complete) = resolution(0, 4);
+		assert_eq!(complete, Resolution::Complete);
+		assert_eq!(&source.text()[locations[0].clone()], "Some");
+		assert_eq!(resolution(2, 7), (vec![2..4, 5..7], Resolution::Partial));
+		// the inserted space falls back to the end of "Some"
+		let (locations, nearest) = resolution(4, 5);
+		assert_eq!(nearest, Resolution::Nearest);
+		assert_eq!(locations.len(), 1);
+		assert_eq!(locations[0], 4..4);
+		assert_eq!(mapping.location(&suggestion(4, 5), &source), locations);
+		assert_eq!(resolution(4, 4), (vec![], Resolution::Complete));
+	}
+
+	#[test]
+	fn test_location_unmerged() {
+		let (doc, source) = compile("A wo#strong[rd] here.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		let start = text.find("word").unwrap();
+		let suggestion = suggestion(start, start + 4);
+		assert_eq!(mapping.location(&suggestion, &source), vec![2..4, 12..14]);
+		assert_eq!(
+			mapping.location_unmerged(&suggestion, &source),
+			vec![2..3, 3..4, 12..13, 13..14]
+		);
+	}
+
+	struct TypoChecker;
+
+	impl crate::Checker for TypoChecker {
+		async fn check(&mut self, _lang: String, text: &str) -> anyhow::Result<Vec<Suggestion>> {
+			let suggestions = text
+				.match_indices("Teh")
+				.map(|(index, _)| {
+					let start = crate::utf_16_index(text, index);
+					Suggestion {
+						message: "Possible typo".into(),
+						replacements: vec!["The".into()],
+						..suggestion(start, start + 3)
+					}
+				})
+				.collect();
+			Ok(suggestions)
 		}
 	}
+
+	#[tokio::test]
+	async fn test_checker() {
+		use crate::Checker;
+
+		let (doc, source) = compile("Über Teh rule.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		let suggestions = TypoChecker
+			.check(mapping.long_language(), text)
+			.await
+			.unwrap();
+		assert_eq!(suggestions.len(), 1);
+		let locations = mapping.location(&suggestions[0], &source);
+		assert_eq!(&source.text()[locations[0].clone()], "Teh");
+	}
+
+	#[test]
+	fn test_language_chunks() {
+		let (doc, source) = compile(
+			"#set text(lang: \"de\")\nEin deutscher Satz.\n\n\
+			 #text(lang: \"en\")[An English sentence.]\n\nNoch ein Satz.",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let chunks = chunks
+			.iter()
+			.map(|(text, mapping)| (text.trim(), mapping.long_language()))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			chunks,
+			[
+				("Ein deutscher Satz.", "de-DE".into()),
+				("An English sentence.", "en-GB".into()),
+				("Noch ein Satz.", "de-DE".into()),
+			]
+		);
+	}
+
+	#[test]
+	fn test_language_paragraphs() {
+		let (doc, source) = compile(
+			"An English paragraph.\n\n\
+			 #set text(lang: \"fr\")\nUn paragraphe en français.",
+		);
+		let chunks = document(&doc, 1, &source, &Default::default()).unwrap();
+		let chunks = chunks
+			.iter()
+			.map(|(text, mapping)| {
+				(
+					text.trim(),
+					mapping.short_language(),
+					mapping.long_language(),
+				)
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(
+			chunks,
+			[
+				("An English paragraph.", "en", "en-GB".into()),
+				("Un paragraphe en français.", "fr", "fr-FR".into()),
+			]
+		);
+	}
+
+	#[test]
+	fn test_math_text() {
+		let (doc, source) = compile(r#"The speed $ v = "spd" $ and $"km" / "h"$ but $x^2$ here."#);
+		let options = ConvertOptions { math_text: true, ..Default::default() };
+		let chunks = document(&doc, 1000, &source, &options).unwrap();
+		let (text, mapping) = &chunks[0];
+		assert_eq!(text.trim(), "The speed\n\nspd\n\nand km h but 0 here.");
+		let start = text.find("spd").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 3), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "spd");
+	}
+
+	#[test]
+	fn test_math_placeholder() {
+		let (doc, source) = compile("The value $x^2$ is positive.\n$ x = 1 $\nAfter the block.");
+		let options = ConvertOptions {
+			math_placeholder: "X".into(),
+			..Default::default()
+		};
+		let chunks = document(&doc, 1000, &source, &options).unwrap();
+		let (text, mapping) = &chunks[0];
+		assert_eq!(
+			text.trim_start(),
+			"The value X is positive.\n\nAfter the block."
+		);
+		let start = text.find('X').unwrap();
+		assert_eq!(located(mapping, &source, start, 1), ["$x^2$"]);
+
+		let (doc, source) = compile("Then $a^2 + b^2 = c^2$ holds.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks[0].0.trim_start(), "Then 0 holds.");
+	}
+
+	#[test]
+	fn test_skip_code() {
+		let (doc, source) = compile(
+			"Call the `fn_name` function here.\n\n```\nlet a = 1;\nlet b = 2;\n```\n\nAfter the block.",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		assert_eq!(
+			text.trim_start(),
+			"Call the 0 function here.\n\n0\n\nAfter the block."
+		);
+		let start = text.find('0').unwrap();
+		assert_eq!(located(mapping, &source, start, 1), ["`fn_name`"]);
+
+		let options = ConvertOptions { skip_code: false, ..Default::default() };
+		let (doc, source) = compile("Call the `fn_name` function here.");
+		let chunks = document(&doc, 1000, &source, &options).unwrap();
+		assert_eq!(chunks[0].0.trim_start(), "Call the fn_name function here.");
+	}
+
+	#[test]
+	fn test_skip_kinds() {
+		let (doc, source) =
+			compile("#figure(rect[], caption: [A cat]) <fig:cat>\n\nThe cat in @fig:cat is cute.");
+		let chunk = |options: &ConvertOptions| {
+			document(&doc, 1000, &source, options)
+				.unwrap()
+				.into_iter()
+				.find(|(text, _)| text.contains("cute"))
+				.unwrap()
+		};
+		assert_eq!(
+			chunk(&ConvertOptions::default()).0.trim(),
+			"The cat in Figure\u{a0}1 is cute."
+		);
+		let options = ConvertOptions {
+			skip_kinds: HashSet::from([SyntaxKind::Ref]),
+			..Default::default()
+		};
+		let (text, mapping) = chunk(&options);
+		assert_eq!(text.trim(), "The cat in 0 is cute.");
+		let start = text.find('0').unwrap();
+		let locations = mapping.location(&suggestion(start, start + 1), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "@fig:cat");
+	}
+
+	#[tokio::test]
+	async fn test_skip_links() {
+		use crate::Checker;
+
+		let (doc, source) = compile("Visit #link(\"https://example.com\")[Teh site] for Teh news.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		assert_eq!(text.trim_start(), "Visit 0 for Teh news.");
+		let suggestions = TypoChecker
+			.check(mapping.long_language(), text)
+			.await
+			.unwrap();
+		assert_eq!(suggestions.len(), 1);
+		let locations = mapping.location(&suggestions[0], &source);
+		assert_eq!(&source.text()[locations[0].clone()], "Teh");
+
+		let (doc, source) = compile("Or https://example.com directly.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks[0].0.trim_start(), "Or 0 directly.");
+
+		let options = ConvertOptions { skip_links: false, ..Default::default() };
+		let (doc, source) = compile("Visit #link(\"https://example.com\")[the site] here.");
+		let chunks = document(&doc, 1000, &source, &options).unwrap();
+		assert_eq!(chunks[0].0.trim_start(), "Visit the site here.");
+	}
+
+	#[test]
+	fn test_caption() {
+		let (doc, source) = compile(
+			"Some text before.\n\n#figure(rect(), caption: [A small cat.])\nNext paragraph here.",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let chunks = chunks
+			.iter()
+			.map(|(text, _)| text.trim())
+			.collect::<Vec<_>>();
+		assert_eq!(
+			chunks,
+			[
+				"Some text before.",
+				"Figure\u{a0}1: A small cat.",
+				"Next paragraph here."
+			]
+		);
+	}
+
+	#[test]
+	fn test_table_cells() {
+		let (doc, source) = compile(
+			"#table(columns: 2, inset: 0pt, stroke: none, [Alpha beta], [Gamma *delta*], [Epsilon], [Zeta])",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(
+			chunks[0].0.trim(),
+			"Alpha beta\n\nGamma delta\n\nEpsilon\n\nZeta"
+		);
+	}
+
+	#[test]
+	fn test_list_items() {
+		let (doc, source) =
+			compile("Shopping:\n- Buy milk\n- Buy eggs\n- Buy bread\n\n+ First\n+ Second");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(
+			chunks[0].0.trim(),
+			"Shopping:\n\nBuy milk\n\nBuy eggs\n\nBuy bread\n\nFirst\n\nSecond"
+		);
+	}
+
+	#[test]
+	fn test_context() {
+		let (doc, source) = compile("First line.\nA line with an eror in it.\nLast line.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		let start = text.find("eror").unwrap();
+		let (snippet, highlight) = mapping
+			.context(&suggestion(start, start + 4), &source)
+			.unwrap();
+		assert_eq!(snippet, "A line with an eror in it.");
+		assert_eq!(highlight, 15..19);
+		assert_eq!(&snippet[highlight], "eror");
+
+		let start = text.find("it.").unwrap();
+		let end = text.find("Last").unwrap() + 4;
+		let (snippet, highlight) = mapping.context(&suggestion(start, end), &source).unwrap();
+		assert_eq!(snippet, "A line with an eror in it.\nLast line.");
+		assert_eq!(&snippet[highlight], "it.\nLast");
+	}
+
+	#[test]
+	fn test_empty_document() {
+		let (_, source) = compile("");
+		let doc = Document::default();
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert!(chunks.is_empty());
+		assert_eq!(
+			summary(&doc, &chunks),
+			Summary { pages: 0, contains_file: false }
+		);
+
+		let (doc, source) = compile("#hide[Some hidden text.]");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert!(chunks.is_empty());
+		assert_eq!(
+			summary(&doc, &chunks),
+			Summary { pages: 1, contains_file: false }
+		);
+
+		let (doc, source) = compile("Some text.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(
+			summary(&doc, &chunks),
+			Summary { pages: 1, contains_file: true }
+		);
+	}
+
+	#[test]
+	fn test_hidden_content() {
+		let (doc, source) = compile("Visible text.\n\n#hide[Hidden typo here.]");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks[0].0.trim(), "Visible text.");
+
+		// typst removes hidden text from the layout, it can only be shown for the spellchecker
+		let (doc, source) = compile(
+			"#show hide: it => if sys.inputs.at(\"spellcheck\", default: false) { it.body } else { it }\n\
+			 Visible text.\n\n#hide[Hidden typo here.]",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		assert_eq!(text.trim(), "Visible text.\n\nHidden typo here.");
+		let start = text.find("typo").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 4), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "typo");
+	}
+
+	#[test]
+	fn test_alt_text() {
+		let (doc, source) = compile(
+			"Some text.\n\n#image.decode(\"<svg xmlns='http://www.w3.org/2000/svg' width='10' height='10'/>\", alt: \"A grphic of a cat.\")\n\nMore text.",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert!(chunks.iter().all(|(text, _)| text.contains("grphic").not()));
+
+		let options = ConvertOptions { alt_text: true, ..Default::default() };
+		let chunks = document(&doc, 1000, &source, &options).unwrap();
+		assert_eq!(
+			chunks
+				.iter()
+				.map(|(text, _)| text.trim())
+				.collect::<Vec<_>>(),
+			["Some text.", "A grphic of a cat.", "More text."]
+		);
+		let (text, mapping) = &chunks[1];
+		let start = text.find("grphic").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 6), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "grphic");
+		let (range, replacement) =
+			apply_suggestion(&suggestion(start, start + 6), "graphic", mapping, &source).unwrap();
+		assert_eq!(&source.text()[range], "grphic");
+		assert_eq!(replacement, "graphic");
+	}
+
+	#[test]
+	fn test_bibliography() {
+		let root = std::env::temp_dir().join(format!(
+			"typst-languagetool-bibliography-{}",
+			std::process::id()
+		));
+		std::fs::create_dir_all(&root).unwrap();
+		std::fs::write(
+			root.join("refs.bib"),
+			"@book{knuth, title = {Die Kunst des Programmierens}, author = {Knuth, Donald}, year = \
+			 {1968}}",
+		)
+		.unwrap();
+		let main = root.join("main.typ");
+		let mut world = lt_world::LtWorld::new(main.clone(), None);
+		world.use_shadow_file(
+			&main,
+			"Some text @knuth.\n\n#bibliography(\"refs.bib\")\n\nMore text.".into(),
+		);
+		let doc = world.compile().unwrap();
+		let source = world.main();
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks.len(), 1);
+		assert_eq!(chunks[0].0.trim(), "Some text [1].\n\nMore text.");
+		assert!(chunks[0].1.is_bibliography().not());
+
+		let options = ConvertOptions { bibliography: true, ..Default::default() };
+		let chunks = document(&doc, 1000, &source, &options).unwrap();
+		assert_eq!(
+			chunks
+				.iter()
+				.map(|(text, mapping)| (text.trim(), mapping.is_bibliography()))
+				.collect::<Vec<_>>(),
+			[
+				("Some text [1].", false),
+				(
+					"Bibliography\n\n[1]\n\nD. Knuth, Die Kunst des Programmierens. 1968.",
+					true
+				),
+				("More text.", false),
+			]
+		);
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn test_string_offsets() {
+		let (doc, source) = compile("Say #\"hello world\" now.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		let start = text.find("world").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 5), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "world");
+	}
+
+	#[test]
+	fn test_ignore_directives() {
+		let (doc, source) = compile(
+			"Good words.\n// languagetool-ignore-next-line\nSome jargn here.\nMore good words.",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks[0].0.trim(), "Good words. More good words.");
+
+		let (doc, source) = compile(
+			"Before the block.\n\n#[Ignored jargn.\n\nAnd more.] <languagetool-ignore>\n\nAfter the block.",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks[0].0.trim(), "Before the block.\n\nAfter the block.");
+
+		let (doc, source) = compile("Before #[the jargn] <languagetool-ignore> after.");
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks[0].0.trim(), "Before after.");
+	}
+
+	#[test]
+	fn test_location_files() {
+		let main = PathBuf::from("/typst-languagetool/main.typ");
+		let other = PathBuf::from("/typst-languagetool/other.typ");
+		let mut world = lt_world::LtWorld::new(main.clone(), None);
+		world.use_shadow_file(&main, "Root text.\n\n#include \"other.typ\"".into());
+		world.use_shadow_file(&other, "Included tpyo.".into());
+		let doc = world.compile().unwrap();
+		let source = world.main();
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		let (text, mapping) = &chunks[0];
+		let start = text.find("tpyo").unwrap();
+		let suggestion = suggestion(start, start + 4);
+		assert!(mapping.location(&suggestion, &source).is_empty());
+
+		let locations = mapping.location_files(&suggestion, |id| world.source(id).ok());
+		assert_eq!(locations.len(), 1);
+		let (id, range) = locations[0].clone();
+		assert_eq!(id, world.file_id(&other));
+		assert_eq!(&world.source(id).unwrap().text()[range], "tpyo");
+	}
+
+	#[test]
+	fn test_justified_bold() {
+		let words = "Lorem ipsum dolor sit amet consectetur adipiscing elit sed do eiusmod tempor \
+			incididunt ut labore et dolore magna aliqua Ut enim ad minim veniam quis nostrud";
+		let text = words
+			.split(' ')
+			.enumerate()
+			.map(|(index, word)| match index % 5 {
+				0 => format!("*{word}*"),
+				_ => word.into(),
+			})
+			.collect::<Vec<_>>()
+			.join(" ");
+		let (doc, source) = compile(&format!("#set par(justify: true)\n{text}"));
+		let text = |options| document(&doc, 1000, &source, &options).unwrap().remove(0).0;
+		assert_eq!(text(ConvertOptions::default()).trim(), words);
+
+		let options = ConvertOptions {
+			y_tolerance: Em::zero(),
+			..Default::default()
+		};
+		assert!(text(options).contains("\n\n"));
+	}
+
+	#[test]
+	fn test_chunk_strategy() {
+		let en = "Some words in a paragraph that is written in English.";
+		let zh = "这是一个用中文写的段落。";
+		assert_eq!(word_count(en), 10);
+		assert_eq!(word_count(zh), 11);
+		assert_eq!(word_count("中文 and English"), 4);
+
+		let text = [en, zh, en, zh, en, zh].join("\n\n");
+		let (doc, source) = compile(&text);
+		let chunks = |chunk_strategy| {
+			let options = ConvertOptions { chunk_strategy, ..Default::default() };
+			document(&doc, 15, &source, &options).unwrap().len()
+		};
+		assert_eq!(chunks(ChunkStrategy::Chars), 4);
+		assert_eq!(chunks(ChunkStrategy::Words), 3);
+	}
+
+	#[test]
+	fn test_unlimited_chunk_size() {
+		let paragraph = "Some words in a paragraph that is long enough to exceed the chunk size.";
+		let (doc, source) = compile(&vec![paragraph; 20].join("\n\n"));
+		let options = ConvertOptions::default();
+		assert!(document(&doc, 100, &source, &options).unwrap().len() > 1);
+
+		let chunks = document(&doc, 0, &source, &options).unwrap();
+		assert_eq!(chunks.len(), 1);
+		assert_eq!(chunks[0].0.matches(paragraph).count(), 20);
+	}
+
+	#[test]
+	fn test_pack_paragraphs() {
+		let paragraph = "Some words in a paragraph that is long enough to exceed the chunk size.";
+		let mut paragraphs = vec![paragraph; 20];
+		paragraphs[18] = "The last paragraphs have Teh typo.";
+		let (doc, source) = compile(&paragraphs.join("\n\n"));
+		let split = document(&doc, 100, &source, &ConvertOptions::default()).unwrap();
+		assert_eq!(split.len(), 10);
+
+		// the context repeated at the start of chunks leaves less room for the paragraphs
+		for (overlap, count) in [(0, 5), (20, 6)] {
+			let options = ConvertOptions {
+				pack_paragraphs: true,
+				max_chunk_size: 300,
+				overlap,
+				..Default::default()
+			};
+			let chunks = document(&doc, 100, &source, &options).unwrap();
+			assert_eq!(chunks.len(), count);
+			let mut checked = Vec::new();
+			for (text, mapping) in &chunks {
+				assert!(text.len() <= 300);
+				checked.extend(mapping.checked_text(text).trim().split("\n\n"));
+			}
+			assert_eq!(checked, paragraphs);
+
+			let (text, mapping) = chunks.last().unwrap();
+			let start = crate::utf_16_index(text, text.find("Teh").unwrap());
+			let locations = mapping.location(&suggestion(start, start + 3), &source);
+			assert_eq!(&source.text()[locations[0].clone()], "Teh");
+		}
+	}
+
+	#[test]
+	fn test_kerning_split() {
+		let (doc, source) = compile(
+			"A wo#text(features: (\"smcp\",))[rd] and ke#h(0.05em)#text(font: \"DejaVu \
+			 Sans\")[rning] here.",
+		);
+		let chunks = document(&doc, 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks[0].0.trim(), "A word and kerning here.");
+	}
+
+	#[test]
+	fn test_drop_cap() {
+		let (doc, source) = compile(
+			"#set page(width: 8cm)\n#let dropcap(letter) = place(top + left, text(size: 3em, \
+			 letter))\n#dropcap[O]#h(1.5em)nce upon a time there was a long paragraph of words \
+			 wrapping around the letter.\n\n#text(size: 3em)[A]\n\nnew paragraph.",
+		);
+		let (text, mapping) =
+			&document(&doc, 1000, &source, &ConvertOptions::default()).unwrap()[0];
+		assert_eq!(
+			text,
+			"\n\nOnce upon a time there was a long paragraph of words wrapping around the \
+			 letter.\n\nA\n\nnew paragraph."
+		);
+		let start = text.find("Once").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 4), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "O");
+		assert_eq!(&source.text()[locations[1].clone()], "nce");
+	}
+
+	#[test]
+	fn test_join_policy() {
+		/// Keeps headings in the sentence of their paragraph and never starts a new chunk.
+		#[derive(Debug)]
+		struct Inline(DefaultJoin);
+
+		impl JoinPolicy for Inline {
+			fn join(&self, prev: &Context, next: &Context) -> Join {
+				if prev.heading.is_some() && next.heading.is_none() {
+					return Join::Space;
+				}
+				match self.0.join(prev, next) {
+					Join::ParBreak => Join::SentenceBreak,
+					join => join,
+				}
+			}
+		}
+
+		let (doc, source) = compile("= Title\nSome text.\n\nNext paragraph.");
+		let texts = |options: &ConvertOptions| {
+			document(&doc, 1, &source, options)
+				.unwrap()
+				.into_iter()
+				.map(|(text, _)| text.trim().to_string())
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(
+			texts(&Default::default()),
+			["Title", "Some text.", "Next paragraph."]
+		);
+		let options = ConvertOptions {
+			join: Some(Arc::new(Inline(DefaultJoin::new(&Default::default())))),
+			..Default::default()
+		};
+		assert_eq!(texts(&options), ["Title Some text.\n\nNext paragraph."]);
+	}
+
+	#[test]
+	fn test_faithful_spacing() {
+		let (doc, source) = compile(
+			"#set page(width: 4cm)\nA  double space and\nmany more words wrapped over lines.",
+		);
+		let (text, _) = &document(&doc, 1000, &source, &ConvertOptions::default()).unwrap()[0];
+		assert!(text.contains("  ").not());
+
+		let options = ConvertOptions {
+			faithful_spacing: true,
+			..Default::default()
+		};
+		let (faithful, mapping) = &document(&doc, 1000, &source, &options).unwrap()[0];
+		let start = faithful.find("  ").unwrap();
+		let double = suggestion(start, start + 2);
+		assert!(mapping.is_inserted(&double).not());
+		let locations = mapping.location(&double, &source);
+		assert_eq!(&source.text()[locations[0].clone()], "  ");
+		// the same words, separated by the whitespace of the source
+		assert!(faithful.contains("and\nmany"));
+		assert_eq!(
+			faithful.split_whitespace().collect::<Vec<_>>(),
+			text.split_whitespace().collect::<Vec<_>>()
+		);
+	}
 }
@@ -0,0 +1,302 @@
+use std::ops::Range;
+
+use typst::syntax::Span;
+
+/// Span and byte range within it of every UTF-16 code unit of a chunk.
+///
+/// Consecutive code units mostly belong to the same span and advance their range by the same
+/// step, so they are stored as runs and each unit is reconstructed on demand.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Chars {
+	runs: Vec<Run>,
+	/// Only count the code units, every unit is stored as a detached one.
+	text_only: bool,
+}
+
+/// Code units with the ranges `start..start + width` advanced by `step` for each unit.
+#[derive(Debug, Clone, PartialEq)]
+struct Run {
+	span: Span,
+	start: u32,
+	width: u32,
+	step: u32,
+	/// Index after the last code unit of the run.
+	end: u32,
+}
+
+impl Run {
+	fn count(&self, begin: u32) -> u32 {
+		self.end - begin
+	}
+
+	fn range(&self, offset: u32) -> Range<u32> {
+		let start = self.start + self.step * offset;
+		start..start + self.width
+	}
+}
+
+impl Chars {
+	/// Chars only counting the code units, for conversions without a mapping.
+	pub fn text_only() -> Self {
+		Self { runs: Vec::new(), text_only: true }
+	}
+
+	pub fn is_text_only(&self) -> bool {
+		self.text_only
+	}
+
+	/// Number of code units.
+	pub fn len(&self) -> usize {
+		self.runs.last().map_or(0, |run| run.end as usize)
+	}
+
+	/// Capacity of the runs, kept when cleared and by [`Self::take`].
+	#[cfg(test)]
+	pub fn capacity(&self) -> usize {
+		self.runs.capacity()
+	}
+
+	/// Bytes used for the runs.
+	#[cfg(test)]
+	pub fn heap_size(&self) -> usize {
+		self.runs.len() * std::mem::size_of::<Run>()
+	}
+
+	pub fn clear(&mut self) {
+		self.runs.clear();
+	}
+
+	/// Takes the chars, leaving empty ones in the same mode with the capacity of the taken ones.
+	pub fn take(&mut self) -> Self {
+		let runs = Vec::with_capacity(self.runs.capacity());
+		Self {
+			runs: std::mem::replace(&mut self.runs, runs),
+			text_only: self.text_only,
+		}
+	}
+
+	pub fn push(&mut self, (span, range): (Span, Range<u32>)) {
+		let end = self.len() as u32 + 1;
+		if self.text_only {
+			// all units share a single detached run
+			match self.runs.last_mut() {
+				Some(last) => last.end = end,
+				None => self.runs.push(Run {
+					span: Span::detached(),
+					start: 0,
+					width: 0,
+					step: 0,
+					end,
+				}),
+			}
+			return;
+		}
+		let begin = self.begin(self.runs.len().saturating_sub(1));
+		if let Some(last) = self.runs.last_mut() {
+			let width = range.end - range.start;
+			if last.span == span && last.width == width && range.start >= last.start {
+				let count = last.count(begin);
+				let step = range.start - last.start;
+				// the second unit of a run sets its step
+				if count == 1 {
+					last.step = step;
+					last.end = end;
+					return;
+				}
+				if range.start == last.range(count - 1).start + last.step {
+					last.end = end;
+					return;
+				}
+			}
+		}
+		self.runs.push(Run {
+			span,
+			start: range.start,
+			width: range.end - range.start,
+			step: 0,
+			end,
+		});
+	}
+
+	pub fn pop(&mut self) -> Option<(Span, Range<u32>)> {
+		let index = self.runs.len().checked_sub(1)?;
+		let begin = self.begin(index);
+		let run = &mut self.runs[index];
+		let last = (run.span, run.range(run.count(begin) - 1));
+		run.end -= 1;
+		if run.end == begin {
+			self.runs.pop();
+		}
+		Some(last)
+	}
+
+	pub fn last(&self) -> Option<(Span, Range<u32>)> {
+		let index = self.runs.len().checked_sub(1)?;
+		let run = &self.runs[index];
+		Some((run.span, run.range(run.count(self.begin(index)) - 1)))
+	}
+
+	/// Replaces the last code unit.
+	pub fn set_last(&mut self, unit: (Span, Range<u32>)) {
+		if self.pop().is_some() {
+			self.push(unit);
+		}
+	}
+
+	/// The code units in the range of indices.
+	pub fn range(&self, range: Range<usize>) -> impl Iterator<Item = (Span, Range<u32>)> + '_ {
+		let first = self
+			.runs
+			.partition_point(|run| run.end as usize <= range.start);
+		let skip = range.start - self.begin(first) as usize;
+		self.runs[first..]
+			.iter()
+			.enumerate()
+			.flat_map(move |(i, run)| {
+				let begin = self.begin(first + i);
+				(0..run.count(begin)).map(|offset| (run.span, run.range(offset)))
+			})
+			.skip(skip)
+			.take(range.end.saturating_sub(range.start))
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (Span, Range<u32>)> + '_ {
+		self.range(0..self.len())
+	}
+
+	/// Copy of the code units in the range of indices.
+	pub fn slice(&self, range: Range<usize>) -> Self {
+		let mut chars = Self {
+			runs: Vec::new(),
+			text_only: self.text_only,
+		};
+		chars.extend(self.range(range));
+		chars
+	}
+
+	/// Removes the first code units, keeps the capacity.
+	pub fn remove_start(&mut self, count: usize) {
+		let rest = self.slice(count..self.len());
+		self.clear();
+		self.runs.extend(rest.runs);
+	}
+
+	/// Index of the first code unit of the run.
+	fn begin(&self, index: usize) -> u32 {
+		match index.checked_sub(1) {
+			Some(previous) => self.runs[previous].end,
+			None => 0,
+		}
+	}
+}
+
+impl Extend<(Span, Range<u32>)> for Chars {
+	fn extend<T: IntoIterator<Item = (Span, Range<u32>)>>(&mut self, iter: T) {
+		for unit in iter {
+			self.push(unit);
+		}
+	}
+}
+
+impl FromIterator<(Span, Range<u32>)> for Chars {
+	fn from_iter<T: IntoIterator<Item = (Span, Range<u32>)>>(iter: T) -> Self {
+		let mut chars = Self::default();
+		chars.extend(iter);
+		chars
+	}
+}
+
+/// Equal if the code units are, regardless of the runs they are stored in.
+impl PartialEq for Chars {
+	fn eq(&self, other: &Self) -> bool {
+		self.len() == other.len() && self.iter().eq(other.iter())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use typst::syntax::{FileId, Source, VirtualPath};
+
+	use super::*;
+
+	fn spans() -> (Span, Span) {
+		let id = FileId::new(None, VirtualPath::new("main.typ"));
+		let source = Source::new(id, "Some *bold* text.".into());
+		let root = typst::syntax::LinkedNode::new(source.root());
+		let mut children = root.children();
+		let text = children.next().unwrap().span();
+		let other = children.nth(1).unwrap().span();
+		(text, other)
+	}
+
+	#[test]
+	fn test_runs() {
+		let (text, other) = spans();
+		let units = vec![
+			(Span::detached(), 0..0),
+			(Span::detached(), 0..0),
+			(text, 0..1),
+			(text, 1..2),
+			(text, 2..4),
+			(text, 2..4),
+			(text, 4..5),
+			(other, 0..1),
+			(other, 3..4),
+			(other, 6..7),
+		];
+		let chars = units.iter().cloned().collect::<Chars>();
+		assert_eq!(chars.len(), units.len());
+		assert_eq!(chars.iter().collect::<Vec<_>>(), units);
+		assert!(chars.runs.len() < units.len());
+		for start in 0..units.len() {
+			for end in start..=units.len() {
+				assert_eq!(
+					chars.range(start..end).collect::<Vec<_>>(),
+					units[start..end],
+					"{start}..{end}"
+				);
+				assert_eq!(
+					chars.slice(start..end).iter().collect::<Vec<_>>(),
+					units[start..end]
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn test_text_only() {
+		let (text, other) = spans();
+		let mut chars = Chars::text_only();
+		chars.extend([(text, 0..1), (other, 3..4), (text, 1..2)]);
+		assert_eq!(chars.len(), 3);
+		assert_eq!(chars.runs.len(), 1);
+		assert_eq!(chars.pop(), Some((Span::detached(), 0..0)));
+		let slice = chars.slice(0..1);
+		assert!(slice.text_only);
+		assert_eq!(slice.iter().collect::<Vec<_>>(), [(Span::detached(), 0..0)]);
+	}
+
+	#[test]
+	fn test_edit() {
+		let (text, other) = spans();
+		let mut chars = (0..4).map(|i| (text, i..i + 1)).collect::<Chars>();
+		assert_eq!(chars.pop(), Some((text, 3..4)));
+		assert_eq!(chars.last(), Some((text, 2..3)));
+		chars.set_last((other, 5..8));
+		chars.push((other, 8..11));
+		chars.remove_start(1);
+		assert_eq!(
+			chars.iter().collect::<Vec<_>>(),
+			[(text, 1..2), (other, 5..8), (other, 8..11)]
+		);
+		assert_eq!(
+			chars,
+			[(text, 1..2), (other, 5..8), (other, 8..11)]
+				.into_iter()
+				.collect()
+		);
+		while chars.pop().is_some() {}
+		assert_eq!(chars.len(), 0);
+		assert_eq!(chars.last(), None);
+	}
+}
@@ -0,0 +1,164 @@
+use std::{fmt::Debug, ops::Not};
+
+use typst::{
+	layout::{Abs, Em, Point},
+	syntax::Span,
+};
+
+use super::{close, ConvertOptions, SENTENCE_END};
+
+// gaps between runs on the same baseline narrower than this are kerning, not a word break,
+// a space is about 0.25em wide and shrinks to about two thirds of it in justified text
+const KERNING_GAP: Em = Em::new(0.1);
+
+/// How the text before and after a boundary between two runs of text is joined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Join {
+	/// The text continues directly, like a word split into several runs.
+	None,
+	/// A space, like between the wrapped lines of a paragraph.
+	Space,
+	/// A newline, like after a manual line break in a poem or an address.
+	LineBreak,
+	/// A break ending the sentence, the chunk continues after it.
+	SentenceBreak,
+	/// A paragraph break, a new chunk can start after it.
+	ParBreak,
+}
+
+/// One side of a boundary between two runs of text.
+///
+/// Fields about the end of the text are only set before the boundary, the others only after it.
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+	/// The text of the chunk before the boundary, or the text of the run after it.
+	pub text: &'a str,
+	/// End of the text before the boundary or start of the text after it, on the baseline.
+	pub pos: Point,
+	pub size: Abs,
+	/// The heading around the text.
+	pub heading: Option<Span>,
+	/// The table cell, grid cell or list item around the text.
+	pub part: Option<Span>,
+	/// The text ends with a wrapped and hyphenated word.
+	pub hyphenated: bool,
+	/// The text ends before a manual line break.
+	pub linebreak: bool,
+	/// The text starts a new column or page.
+	pub flow_break: bool,
+	/// The text is raised or lowered, like a superscript.
+	pub shifted: bool,
+	/// The text continues the syntax node at the end of the previous text.
+	pub continues: bool,
+	/// Distance to the baseline of the next line of a paragraph with the text.
+	pub line_spacing: Abs,
+}
+
+impl Context<'_> {
+	fn ends_sentence(&self) -> bool {
+		self.text.trim_end().ends_with(SENTENCE_END)
+	}
+}
+
+/// Whether the text is an enlarged first letter of a paragraph, like a drop cap, before text of
+/// the size.
+pub(super) fn is_drop_cap(text: &str, size: Abs, next_size: Abs) -> bool {
+	let letters = text.rsplit("\n\n").next().unwrap_or_default().trim_start();
+	letters.is_empty().not()
+		&& letters.chars().count() <= 2
+		&& letters.chars().all(char::is_alphabetic)
+		&& size >= next_size * 2.0
+}
+
+/// Decides how the [`super::Converter`] joins the text at each boundary between runs of text.
+pub trait JoinPolicy: Debug + Send + Sync {
+	fn join(&self, prev: &Context, next: &Context) -> Join;
+}
+
+/// The policy used without [`ConvertOptions::join`], following the layout of the text.
+#[derive(Debug, Clone)]
+pub struct DefaultJoin {
+	pub x_tolerance: Em,
+	pub y_tolerance: Em,
+	pub linebreak_sentences: bool,
+}
+
+impl DefaultJoin {
+	pub fn new(options: &ConvertOptions) -> Self {
+		Self {
+			x_tolerance: options.x_tolerance,
+			y_tolerance: options.y_tolerance,
+			linebreak_sentences: options.linebreak_sentences,
+		}
+	}
+
+	/// Whether the previous text is a drop cap followed by the rest of the word on the lines
+	/// next to it.
+	fn drop_cap(&self, prev: &Context, next: &Context) -> bool {
+		is_drop_cap(prev.text, prev.size, next.size)
+			&& next.text.starts_with(char::is_lowercase)
+			&& next.pos.x + prev.size > prev.pos.x
+			&& next.pos.y < prev.pos.y
+			&& prev.pos.y - next.pos.y < prev.size
+	}
+
+	/// Whether the next text continues the word on the same baseline, after a gap narrower
+	/// than a space, like runs of another font or with other font features.
+	fn kerning(&self, prev: &Context, next: &Context) -> bool {
+		prev.text.ends_with(char::is_alphabetic)
+			&& next.text.starts_with(char::is_alphabetic)
+			&& prev.size.approx_eq(next.size)
+			&& close(prev.pos.y, next.pos.y, self.y_tolerance.at(next.size))
+			&& close(prev.pos.x, next.pos.x, KERNING_GAP.at(next.size))
+	}
+}
+
+impl JoinPolicy for DefaultJoin {
+	fn join(&self, prev: &Context, next: &Context) -> Join {
+		// the text of different cells or list items is never part of the same sentence,
+		// headings always end the previous sentence and start a new one
+		let next_part =
+			(next.part.is_some() && next.part != prev.part) || next.heading != prev.heading;
+		if next_part && prev.text.is_empty().not() {
+			return Join::ParBreak;
+		}
+		if next.flow_break && prev.text.is_empty().not() {
+			// the text probably continues from the previous column or page
+			return match prev.ends_sentence() {
+				true => Join::ParBreak,
+				false => Join::Space,
+			};
+		}
+		if self.drop_cap(prev, next) {
+			return Join::None;
+		}
+		if close(prev.pos.x, next.pos.x, self.x_tolerance.at(next.size)) {
+			return Join::None;
+		}
+		if self.kerning(prev, next) {
+			return Join::None;
+		}
+		let next_line = next.shifted
+			|| close(
+				prev.pos.y + next.line_spacing,
+				next.pos.y,
+				self.y_tolerance.at(next.size),
+			);
+		// a line with another text size is only a wrapped line if it ended a sentence,
+		// otherwise it is probably a heading followed by the body text
+		let same_paragraph = next.shifted || prev.size.approx_eq(next.size) || prev.ends_sentence();
+		if !next_line || !same_paragraph {
+			return Join::ParBreak;
+		}
+		if prev.hyphenated || next.continues {
+			return Join::None;
+		}
+		if self.linebreak_sentences && prev.ends_sentence().not() && prev.linebreak {
+			return Join::SentenceBreak;
+		}
+		if prev.linebreak {
+			return Join::LineBreak;
+		}
+		Join::Space
+	}
+}
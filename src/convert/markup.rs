@@ -0,0 +1,252 @@
+use std::ops::Not;
+
+use typst::{
+	syntax::{ast, LinkedNode, Source, Span, SyntaxKind},
+	text::Lang,
+};
+
+use super::{chars::Chars, is_call, word_count, ChunkStrategy, ConvertOptions, Mapping};
+
+/// Extracts the text of the markup of the source, without laying it out.
+///
+/// A fallback for documents that fail to compile, like ones with a missing image or font.
+/// Raw text, links and inline equations are replaced with the placeholder like in the layout.
+/// Code is left out except for the markup of its content blocks, and so are block equations.
+/// A new chunk is started at the next paragraph break after `chunk_size` chars, all text is kept
+/// in a single chunk with 0.
+pub fn markup(
+	source: &Source,
+	chunk_size: usize,
+	options: &ConvertOptions,
+) -> Vec<(String, Mapping)> {
+	let mut extractor = Extractor {
+		text: String::new(),
+		chars: Chars::default(),
+		language: language(source),
+		chunk_size,
+		options,
+		res: Vec::new(),
+	};
+	extractor.node(&LinkedNode::new(source.root()));
+	extractor.seperate_chunk();
+	extractor.res
+}
+
+/// The language of the first `set text(lang: ..)` rule, English without one.
+fn language(source: &Source) -> Lang {
+	let mut nodes = vec![LinkedNode::new(source.root())];
+	while let Some(node) = nodes.pop() {
+		let lang = node.cast::<ast::SetRule>().and_then(|set| {
+			let text = matches!(set.target(), ast::Expr::Ident(ident) if ident.as_str() == "text");
+			set.args().items().find_map(|arg| match arg {
+				ast::Arg::Named(named) if text && named.name().as_str() == "lang" => {
+					match named.expr() {
+						ast::Expr::Str(lang) => lang.get().parse().ok(),
+						_ => None,
+					}
+				},
+				_ => None,
+			})
+		});
+		if let Some(lang) = lang {
+			return lang;
+		}
+		// the children are popped in the order of the source
+		nodes.extend(node.children().rev());
+	}
+	Lang::ENGLISH
+}
+
+struct Extractor<'a> {
+	text: String,
+	chars: Chars,
+	language: Lang,
+	chunk_size: usize,
+	options: &'a ConvertOptions,
+	res: Vec<(String, Mapping)>,
+}
+
+impl Extractor<'_> {
+	fn node(&mut self, node: &LinkedNode) {
+		let skip_link =
+			self.options.skip_links && (node.kind() == SyntaxKind::Link || is_call(node, "link"));
+		let skip_code = self.options.skip_code && node.kind() == SyntaxKind::Raw;
+		if skip_link || skip_code || self.options.skip_kinds.contains(&node.kind()) {
+			self.placeholder(node.span());
+			return;
+		}
+		match node.kind() {
+			SyntaxKind::Text => self.push(node.text(), node.span()),
+			SyntaxKind::Space => {
+				if self.text.is_empty().not() && self.text.ends_with(char::is_whitespace).not() {
+					self.push_mapped(' ', node.span(), node.len());
+				}
+			},
+			SyntaxKind::Parbreak => self.parbreak(),
+			SyntaxKind::Linebreak => {
+				self.trim_end();
+				self.push_mapped('\n', node.span(), node.len());
+			},
+			SyntaxKind::Escape => {
+				if let Some(escape) = node.cast::<ast::Escape>() {
+					self.push_mapped(escape.get(), node.span(), node.len());
+				}
+			},
+			SyntaxKind::Shorthand => {
+				if let Some(shorthand) = node.cast::<ast::Shorthand>() {
+					self.push_mapped(shorthand.get(), node.span(), node.len());
+				}
+			},
+			SyntaxKind::SmartQuote => {
+				let quote = if node.text() == "'" { '\'' } else { '"' };
+				self.push_mapped(quote, node.span(), node.len());
+			},
+			SyntaxKind::Equation => {
+				if node
+					.cast::<ast::Equation>()
+					.is_some_and(|eq| eq.block().not())
+				{
+					self.placeholder(node.span());
+				}
+			},
+			// the generated text of references is only known after the layout
+			SyntaxKind::Ref => self.placeholder(node.span()),
+			SyntaxKind::Link => self.push(node.text(), node.span()),
+			SyntaxKind::Heading
+			| SyntaxKind::ListItem
+			| SyntaxKind::EnumItem
+			| SyntaxKind::TermItem => {
+				self.parbreak();
+				self.children(node);
+				self.parbreak();
+			},
+			// the markers of headings and list items are not part of the text
+			SyntaxKind::HeadingMarker
+			| SyntaxKind::ListMarker
+			| SyntaxKind::EnumMarker
+			| SyntaxKind::TermMarker
+			| SyntaxKind::Label
+			| SyntaxKind::LineComment
+			| SyntaxKind::BlockComment => {},
+			// definitions and rules do not show their content where they are written
+			SyntaxKind::LetBinding
+			| SyntaxKind::SetRule
+			| SyntaxKind::ShowRule
+			| SyntaxKind::ModuleImport
+			| SyntaxKind::ModuleInclude => {},
+			// the text of raw blocks, or of content blocks within code
+			_ => self.children(node),
+		}
+	}
+
+	fn children(&mut self, node: &LinkedNode) {
+		for child in node.children() {
+			if child.kind().is_trivia() && node.kind() != SyntaxKind::Markup {
+				continue;
+			}
+			self.node(&child);
+		}
+	}
+
+	/// Appends the text of a text node, mapped to its bytes.
+	fn push(&mut self, text: &str, span: Span) {
+		self.text += text;
+		for (index, c) in text.char_indices() {
+			for _ in 0..c.len_utf16() {
+				self.chars
+					.push((span, index as u32..(index + c.len_utf8()) as u32));
+			}
+		}
+	}
+
+	/// Appends a char mapped to the whole node.
+	fn push_mapped(&mut self, c: char, span: Span, len: usize) {
+		self.text.push(c);
+		for _ in 0..c.len_utf16() {
+			self.chars.push((span, 0..len as u32));
+		}
+	}
+
+	fn placeholder(&mut self, span: Span) {
+		self.text += &self.options.math_placeholder;
+		for _ in self.options.math_placeholder.encode_utf16() {
+			self.chars.push((span, 0..0));
+		}
+	}
+
+	fn trim_end(&mut self) {
+		if self.text.ends_with(' ') {
+			self.text.pop();
+			self.chars.pop();
+		}
+	}
+
+	fn parbreak(&mut self) {
+		self.trim_end();
+		if self.text.trim().is_empty() || self.text.ends_with("\n\n") {
+			return;
+		}
+		let chunk_len = match self.options.chunk_strategy {
+			ChunkStrategy::Chars => self.chars.len(),
+			ChunkStrategy::Words => word_count(&self.text),
+		};
+		if self.chunk_size != 0 && chunk_len > self.chunk_size {
+			self.seperate_chunk();
+			return;
+		}
+		self.text += "\n\n";
+		self.chars.push((Span::detached(), 0..0));
+		self.chars.push((Span::detached(), 0..0));
+	}
+
+	fn seperate_chunk(&mut self) {
+		self.trim_end();
+		if self.text.ends_with("\n\n") {
+			self.text.truncate(self.text.len() - 2);
+			self.chars.pop();
+			self.chars.pop();
+		}
+		let text = std::mem::take(&mut self.text);
+		let chars = std::mem::take(&mut self.chars);
+		if text.trim().is_empty() {
+			return;
+		}
+		// chunks end at paragraph breaks, so no sentence continues in the next one
+		let mapping = Mapping { chars, ..Mapping::new(self.language) };
+		self.res.push((text, mapping));
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use typst::World;
+
+	use super::*;
+	use crate::convert::{
+		self,
+		test::{suggestion, world},
+	};
+
+	#[test]
+	fn test_markup_fallback() {
+		let world = world(
+			"#set text(lang: \"de\")\n= Intro\n\nThe cat _sat_ on teh mat -- with $x$ \
+			 #image(\"missing.png\") and `code`.\n\n- A #strong[list] item\n\n$ x = 1 $",
+		);
+		let doc = world.compile();
+		assert!(doc.is_none());
+		let source = world.main();
+		let chunks =
+			convert::document_or_markup(doc.as_ref(), 1000, &source, &Default::default()).unwrap();
+		assert_eq!(chunks.len(), 1);
+		let (text, mapping) = &chunks[0];
+		assert_eq!(
+			text,
+			"Intro\n\nThe cat sat on teh mat – with 0 and 0.\n\nA list item"
+		);
+		assert_eq!(mapping.language, Lang::GERMAN);
+		let start = text.find("teh").unwrap();
+		let locations = mapping.location(&suggestion(start, start + 3), &source);
+		assert_eq!(&source.text()[locations[0].clone()], "teh");
+	}
+}
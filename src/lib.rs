@@ -1,20 +1,96 @@
 mod backends;
 pub mod convert;
+pub mod stats;
 
-use std::ops::{Not, Range};
+use std::{
+	collections::{HashMap, HashSet},
+	fmt::{self, Display, Formatter},
+	hash::{Hash, Hasher},
+	ops::{Not, Range},
+	path::PathBuf,
+	time::{Duration, SystemTime},
+};
 
+#[cfg(any(
+	feature = "bundle-jar",
+	feature = "extern-jar",
+	feature = "remote-server"
+))]
 pub use backends::*;
-use convert::Mapping;
+use convert::{Fnv, Mapping};
 use typst::{
+	diag::FileError,
 	syntax::{FileId, Source},
 	World,
 };
 
+/// Errors of converting and checking documents.
+#[derive(Debug)]
+pub enum Error {
+	/// The source of the checked file could not be loaded.
+	Source(FileError),
+	/// The document could not be converted with the options.
+	Conversion(String),
+	/// The request to the LanguageTool server failed.
+	#[cfg(feature = "remote-server")]
+	Http(reqwest::Error),
+	/// The LanguageTool server did not respond in time.
+	Timeout,
+	/// The LanguageTool server responded with an error status.
+	Status { status: u16, text: String },
+	/// The response of the LanguageTool server could not be parsed.
+	Response(String),
+}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Source(err) => write!(f, "failed to load the source: {err}"),
+			Self::Conversion(err) => write!(f, "failed to convert the document: {err}"),
+			#[cfg(feature = "remote-server")]
+			Self::Http(err) => write!(f, "request to the languagetool server failed: {err}"),
+			Self::Timeout => write!(f, "languagetool server did not respond in time"),
+			Self::Status { status, text } => {
+				write!(f, "languagetool server responded with {status}: {text}")
+			},
+			Self::Response(err) => write!(f, "invalid response of the languagetool server: {err}"),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Source(err) => Some(err),
+			#[cfg(feature = "remote-server")]
+			Self::Http(err) => Some(err),
+			Self::Conversion(_) | Self::Timeout | Self::Status { .. } | Self::Response(_) => None,
+		}
+	}
+}
+
 #[allow(async_fn_in_trait)]
 pub trait LanguageToolBackend {
 	async fn allow_words(&mut self, lang: String, words: &[String]) -> anyhow::Result<()>;
 	async fn disable_checks(&mut self, lang: String, checks: &[String]) -> anyhow::Result<()>;
+	async fn enable_checks(&mut self, lang: String, checks: &[String]) -> anyhow::Result<()>;
 	async fn check_text(&mut self, lang: String, text: &str) -> anyhow::Result<Vec<Suggestion>>;
+	/// Version of LanguageTool, results of other versions can differ.
+	async fn version(&mut self) -> anyhow::Result<String>;
+}
+
+/// A checker for the text of a document, with suggestions in UTF-16 code units.
+///
+/// Implemented by all LanguageTool backends, other checkers can be used the same way.
+#[allow(async_fn_in_trait)]
+pub trait Checker {
+	async fn check(&mut self, lang: String, text: &str) -> anyhow::Result<Vec<Suggestion>>;
+}
+
+impl<T: LanguageToolBackend> Checker for T {
+	async fn check(&mut self, lang: String, text: &str) -> anyhow::Result<Vec<Suggestion>> {
+		self.check_text(lang, text).await
+	}
 }
 
 #[derive(Debug)]
@@ -61,6 +137,15 @@ impl LanguageTool {
 	}
 }
 
+// without any backend feature `LanguageTool` has no variants and the arguments are unused
+#[cfg_attr(
+	not(any(
+		feature = "bundle-jar",
+		feature = "extern-jar",
+		feature = "remote-server"
+	)),
+	allow(unused_variables)
+)]
 impl LanguageToolBackend for LanguageTool {
 	async fn allow_words(&mut self, lang: String, words: &[String]) -> anyhow::Result<()> {
 		match self {
@@ -68,6 +153,12 @@ impl LanguageToolBackend for LanguageTool {
 			Self::JNI(lt) => lt.allow_words(lang, words).await,
 			#[cfg(feature = "remote-server")]
 			Self::Remote(lt) => lt.allow_words(lang, words).await,
+			#[cfg(not(any(
+				feature = "bundle-jar",
+				feature = "extern-jar",
+				feature = "remote-server"
+			)))]
+			_ => unreachable!(),
 		}
 	}
 	async fn disable_checks(&mut self, lang: String, checks: &[String]) -> anyhow::Result<()> {
@@ -76,6 +167,26 @@ impl LanguageToolBackend for LanguageTool {
 			Self::JNI(lt) => lt.disable_checks(lang, checks).await,
 			#[cfg(feature = "remote-server")]
 			Self::Remote(lt) => lt.disable_checks(lang, checks).await,
+			#[cfg(not(any(
+				feature = "bundle-jar",
+				feature = "extern-jar",
+				feature = "remote-server"
+			)))]
+			_ => unreachable!(),
+		}
+	}
+	async fn enable_checks(&mut self, lang: String, checks: &[String]) -> anyhow::Result<()> {
+		match self {
+			#[cfg(any(feature = "bundle-jar", feature = "extern-jar"))]
+			Self::JNI(lt) => lt.enable_checks(lang, checks).await,
+			#[cfg(feature = "remote-server")]
+			Self::Remote(lt) => lt.enable_checks(lang, checks).await,
+			#[cfg(not(any(
+				feature = "bundle-jar",
+				feature = "extern-jar",
+				feature = "remote-server"
+			)))]
+			_ => unreachable!(),
 		}
 	}
 	async fn check_text(&mut self, lang: String, text: &str) -> anyhow::Result<Vec<Suggestion>> {
@@ -84,35 +195,300 @@ impl LanguageToolBackend for LanguageTool {
 			Self::JNI(lt) => lt.check_text(lang, text).await,
 			#[cfg(feature = "remote-server")]
 			Self::Remote(lt) => lt.check_text(lang, text).await,
+			#[cfg(not(any(
+				feature = "bundle-jar",
+				feature = "extern-jar",
+				feature = "remote-server"
+			)))]
+			_ => unreachable!(),
+		}
+	}
+	async fn version(&mut self) -> anyhow::Result<String> {
+		match self {
+			#[cfg(any(feature = "bundle-jar", feature = "extern-jar"))]
+			Self::JNI(lt) => lt.version().await,
+			#[cfg(feature = "remote-server")]
+			Self::Remote(lt) => lt.version().await,
+			#[cfg(not(any(
+				feature = "bundle-jar",
+				feature = "extern-jar",
+				feature = "remote-server"
+			)))]
+			_ => unreachable!(),
+		}
+	}
+}
+
+/// Suggestions of a checked chunk, with what was checked to tell chunks with the same key apart.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct CacheEntry {
+	lang: String,
+	text: String,
+	/// Hash of the rule configuration.
+	rules: u64,
+	suggestions: Vec<Suggestion>,
+}
+
+impl CacheEntry {
+	fn new(lang: &str, text: &str, rules: &impl Hash, suggestions: Vec<Suggestion>) -> Self {
+		Self {
+			lang: lang.into(),
+			text: text.into(),
+			rules: rules_hash(rules),
+			suggestions,
+		}
+	}
+
+	fn matches(&self, lang: &str, text: &str, rules: &impl Hash) -> bool {
+		self.lang == lang && self.text == text && self.rules == rules_hash(rules)
+	}
+}
+
+fn rules_hash(rules: &impl Hash) -> u64 {
+	let mut hasher = Fnv::default();
+	rules.hash(&mut hasher);
+	hasher.finish()
+}
+
+/// Suggestions of checked chunks, keyed by a hash of the text, language and rule configuration.
+#[derive(Debug, Default)]
+pub struct CheckCache {
+	entries: HashMap<u64, CacheEntry>,
+}
+
+impl CheckCache {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// FNV hash of the entry, the same for every build of the tool.
+	fn key(lang: &str, text: &str, rules: &impl Hash) -> u64 {
+		let mut hasher = Fnv::default();
+		lang.hash(&mut hasher);
+		text.hash(&mut hasher);
+		rules.hash(&mut hasher);
+		hasher.finish()
+	}
+
+	/// Cached suggestions, `rules` is everything changing the result like disabled rules.
+	pub fn get(&self, lang: &str, text: &str, rules: &impl Hash) -> Option<&[Suggestion]> {
+		self.entries
+			.get(&Self::key(lang, text, rules))
+			.filter(|entry| entry.matches(lang, text, rules))
+			.map(|entry| entry.suggestions.as_slice())
+	}
+
+	pub fn insert(
+		&mut self,
+		lang: &str,
+		text: &str,
+		rules: &impl Hash,
+		suggestions: Vec<Suggestion>,
+	) {
+		self.entries.insert(
+			Self::key(lang, text, rules),
+			CacheEntry::new(lang, text, rules, suggestions),
+		);
+	}
+}
+
+/// Suggestions of checked chunks stored as files in a folder, to reuse them across runs.
+///
+/// Keyed like [`CheckCache`], entries store the checked text to skip the ones of other chunks
+/// with the same key.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+	dir: PathBuf,
+	max_age: Duration,
+	max_size: u64,
+}
+
+impl DiskCache {
+	/// Uses the folder for the entries, entries unused for 30 days or over a total of 100 MB are
+	/// evicted by default.
+	pub fn new(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+		let dir = dir.into();
+		std::fs::create_dir_all(&dir)?;
+		Ok(Self {
+			dir,
+			max_age: Duration::from_secs(30 * 24 * 60 * 60),
+			max_size: 100_000_000,
+		})
+	}
+
+	/// Entries not used for longer are evicted.
+	pub fn with_max_age(mut self, max_age: Duration) -> Self {
+		self.max_age = max_age;
+		self
+	}
+
+	/// Total size in bytes of the entries, the least recently used ones are evicted beyond it.
+	pub fn with_max_size(mut self, max_size: u64) -> Self {
+		self.max_size = max_size;
+		self
+	}
+
+	fn path(&self, lang: &str, text: &str, rules: &impl Hash) -> PathBuf {
+		let key = CheckCache::key(lang, text, rules);
+		self.dir.join(format!("{key:016x}.json"))
+	}
+
+	/// Cached suggestions, unreadable entries are missing.
+	pub fn get(&self, lang: &str, text: &str, rules: &impl Hash) -> Option<Vec<Suggestion>> {
+		let path = self.path(lang, text, rules);
+		let entry: CacheEntry = serde_json::from_slice(&std::fs::read(&path).ok()?).ok()?;
+		if entry.matches(lang, text, rules).not() {
+			return None;
+		}
+		// the age is counted from the last use
+		if let Ok(file) = std::fs::File::options().append(true).open(&path) {
+			let _ = file.set_modified(SystemTime::now());
+		}
+		Some(entry.suggestions)
+	}
+
+	pub fn insert(
+		&self,
+		lang: &str,
+		text: &str,
+		rules: &impl Hash,
+		suggestions: &[Suggestion],
+	) -> anyhow::Result<()> {
+		let path = self.path(lang, text, rules);
+		let entry = CacheEntry::new(lang, text, rules, suggestions.to_vec());
+		std::fs::write(path, serde_json::to_vec(&entry)?)?;
+		Ok(())
+	}
+
+	/// Removes the entries over the maximal age, then the least recently used ones over the
+	/// maximal size.
+	pub fn evict(&self) -> anyhow::Result<()> {
+		let now = SystemTime::now();
+		let mut entries = Vec::new();
+		for entry in std::fs::read_dir(&self.dir)? {
+			let path = entry?.path();
+			if path.extension().is_some_and(|ext| ext == "json").not() {
+				continue;
+			}
+			let metadata = std::fs::metadata(&path)?;
+			let modified = metadata.modified()?;
+			let age = now.duration_since(modified).unwrap_or_default();
+			if age > self.max_age {
+				std::fs::remove_file(&path)?;
+				continue;
+			}
+			entries.push((modified, metadata.len(), path));
+		}
+		entries.sort();
+		let mut size = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+		for (_, len, path) in entries {
+			if size <= self.max_size {
+				break;
+			}
+			std::fs::remove_file(&path)?;
+			size -= len;
+		}
+		Ok(())
+	}
+}
+
+/// Words like jargon and names whose suggestions are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoredWords {
+	words: HashSet<String>,
+	case_sensitive: bool,
+}
+
+impl IgnoredWords {
+	pub fn new(words: impl IntoIterator<Item = impl Into<String>>, case_sensitive: bool) -> Self {
+		let mut ignored = Self { words: HashSet::new(), case_sensitive };
+		for word in words {
+			let word = ignored.normalize(word.into());
+			ignored.words.insert(word);
+		}
+		ignored
+	}
+
+	pub fn contains(&self, word: &str) -> bool {
+		self.words.contains(&self.normalize(word.into()))
+	}
+
+	fn normalize(&self, word: String) -> String {
+		if self.case_sensitive {
+			word
+		} else {
+			word.to_lowercase()
 		}
 	}
 }
 
 pub struct FileCollector {
 	source: Source,
+	ignored: IgnoredWords,
+	max_length: Option<usize>,
 	diagnostics: Vec<Diagnostic>,
 }
 
 impl FileCollector {
-	pub fn new(file_id: FileId, world: &impl World) -> Self {
-		let source = world.source(file_id).unwrap();
-		Self { source, diagnostics: Vec::new() }
+	pub fn new(file_id: FileId, world: &impl World) -> Result<Self, Error> {
+		let source = world.source(file_id).map_err(Error::Source)?;
+		Ok(Self::from_source(source))
+	}
+
+	/// Collects the diagnostics of the file of the source.
+	pub fn from_source(source: Source) -> Self {
+		Self {
+			source,
+			ignored: IgnoredWords::default(),
+			max_length: None,
+			diagnostics: Vec::new(),
+		}
+	}
+
+	/// Ignore suggestions for the words, compared with the flagged text of the source.
+	pub fn with_ignored_words(mut self, ignored: IgnoredWords) -> Self {
+		self.ignored = ignored;
+		self
+	}
+
+	/// Tag diagnostics spanning more than `max_length` bytes of the source as
+	/// [`Diagnostic::long`], like matches of rules for too long sentences.
+	pub fn with_max_length(mut self, max_length: usize) -> Self {
+		self.max_length = Some(max_length);
+		self
 	}
 
 	pub fn add(&mut self, suggestions: &[Suggestion], mapping: Mapping) {
 		let diagnostics = suggestions
 			.iter()
+			.filter(|suggestion| mapping.is_context(suggestion).not())
+			.filter(|suggestion| mapping.is_inserted(suggestion).not())
 			.map(|suggestion| {
 				let locations = mapping.location(suggestion, &self.source);
+				let length = match (locations.first(), locations.last()) {
+					(Some(first), Some(last)) => last.end - first.start,
+					_ => 0,
+				};
 				Diagnostic {
+					long: self.max_length.is_some_and(|max| length > max),
 					locations,
 					message: suggestion.message.clone(),
+					short_message: suggestion.short_message.clone(),
 					replacements: suggestion.replacements.clone(),
 					rule_description: suggestion.rule_description.clone(),
 					rule_id: suggestion.rule_id.clone(),
+					category: suggestion.category.clone(),
 				}
 			})
-			.filter(|diagnostic| diagnostic.locations.is_empty().not());
+			.filter(|diagnostic| diagnostic.locations.is_empty().not())
+			.filter(|diagnostic| {
+				let text = diagnostic
+					.locations
+					.iter()
+					.map(|range| &self.source.text()[range.clone()])
+					.collect::<String>();
+				self.ignored.contains(&text).not()
+			});
 		self.diagnostics.extend(diagnostics)
 	}
 
@@ -125,19 +501,95 @@ impl FileCollector {
 pub struct Diagnostic {
 	pub locations: Vec<Range<usize>>,
 	pub message: String,
+	/// Shorter variant of the message, may be empty.
+	pub short_message: String,
 	pub replacements: Vec<String>,
 	pub rule_description: String,
 	pub rule_id: String,
+	/// Id of the rule category, like `TYPOS` or `GRAMMAR`.
+	pub category: String,
+	/// Spans more than the maximal length of the [`FileCollector`], better shown as a hint for
+	/// its lines than underlined.
+	pub long: bool,
 }
 
-#[derive(Debug, Clone)]
+impl Diagnostic {
+	/// The edit of the source replacing the text of the diagnostic.
+	pub fn apply(&self, replacement: &str) -> Result<(Range<usize>, String), convert::ApplyError> {
+		convert::apply_locations(&self.locations, replacement)
+	}
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Suggestion {
+	/// Start in UTF-16 code units of the checked text, as reported by LanguageTool.
 	pub start: usize,
+	/// End in UTF-16 code units of the checked text, as reported by LanguageTool.
 	pub end: usize,
 	pub message: String,
+	/// Shorter variant of the message, may be empty.
+	pub short_message: String,
+	/// Replacements for the text of the suggestion, the most likely first.
 	pub replacements: Vec<String>,
 	pub rule_description: String,
 	pub rule_id: String,
+	/// Id of the rule category, like `TYPOS` or `GRAMMAR`.
+	pub category: String,
+}
+
+impl Suggestion {
+	/// The range of the suggestion in bytes of the checked text.
+	pub fn utf_8_range(&self, text: &str) -> Range<usize> {
+		utf_8_index(text, self.start)..utf_8_index(text, self.end)
+	}
+}
+
+/// Keeps only the suggestions with one of the allowed categories.
+pub fn filter_by_category(
+	suggestions: Vec<Suggestion>,
+	allowed: &[impl AsRef<str>],
+) -> Vec<Suggestion> {
+	suggestions
+		.into_iter()
+		.filter(|suggestion| {
+			allowed
+				.iter()
+				.any(|category| category.as_ref() == suggestion.category)
+		})
+		.collect()
+}
+
+/// Converts an offset in UTF-16 code units to a byte offset, clamped to the text.
+pub fn utf_8_index(text: &str, utf_16_index: usize) -> usize {
+	let mut utf_16 = 0;
+	for (index, c) in text.char_indices() {
+		if utf_16 >= utf_16_index {
+			return index;
+		}
+		utf_16 += c.len_utf16();
+	}
+	text.len()
+}
+
+/// Converts a byte offset to an offset in UTF-16 code units.
+pub fn utf_16_index(text: &str, utf_8_index: usize) -> usize {
+	text[..utf_8_index].encode_utf16().count()
+}
+
+/// Zero based line and column of the start and end of the range in the source.
+///
+/// Columns are counted in chars, not bytes or graphemes.
+pub fn line_column(
+	source: &Source,
+	range: Range<usize>,
+) -> Option<((usize, usize), (usize, usize))> {
+	let position = |index| {
+		let line = source.byte_to_line(index)?;
+		let start = source.line_to_byte(line)?;
+		let column = source.get(start..index)?.chars().count();
+		Some((line, column))
+	};
+	Some((position(range.start)?, position(range.end)?))
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -188,7 +640,7 @@ impl<'a> TextWithPosition<'a> {
 					},
 				}
 			}
-		} else if end > start {
+		} else if start > end {
 			for c in self.content.text[end..start].chars() {
 				match c {
 					'\n' => {
@@ -257,6 +709,7 @@ impl<'a> StringCursor<'a> {
 #[cfg(test)]
 mod test {
 	use super::*;
+	use crate::convert::test::{suggestion, world};
 
 	#[test]
 	fn test_variable_width() {
@@ -270,6 +723,224 @@ mod test {
 		assert_eq!(cursor.utf_8_offset(3, false), None);
 	}
 
+	#[test]
+	fn test_position_backwards() {
+		let mut text = TextWithPosition::new("abc\ndef ghi");
+		let position = text.get_position(9, false);
+		assert_eq!((position.utf_8, position.line, position.column), (9, 1, 5));
+		// moving back in the line counts the column down
+		let position = text.get_position(5, false);
+		assert_eq!((position.utf_8, position.line, position.column), (5, 1, 1));
+	}
+
+	#[test]
+	fn test_utf_16_index() {
+		let text = "a😀b";
+		assert_eq!(utf_16_index(text, 5), 3);
+		assert_eq!(utf_8_index(text, 3), 5);
+		assert_eq!(utf_8_index(text, 1), 1);
+		assert_eq!(utf_8_index(text, 10), 6);
+	}
+
+	#[test]
+	fn test_line_column() {
+		let source = Source::detached("First line.\nSecond line.\nThé ärror\nhere.");
+		let start = source.text().find("ärror").unwrap();
+		assert_eq!(
+			line_column(&source, start..start + 6),
+			Some(((2, 4), (2, 9)))
+		);
+		let end = source.text().find("here").unwrap();
+		assert_eq!(line_column(&source, start..end), Some(((2, 4), (3, 0))));
+		assert_eq!(line_column(&source, 0..100), None);
+	}
+
+	#[test]
+	fn test_check_cache() {
+		let mut cache = CheckCache::new();
+		cache.insert("en-US", "Teh text.", &["RULE"], vec![suggestion(0, 3)]);
+		assert_eq!(cache.get("en-US", "Teh text.", &["RULE"]).unwrap().len(), 1);
+		assert!(cache.get("en-US", "Teh text.", &["OTHER_RULE"]).is_none());
+		assert!(cache.get("de-DE", "Teh text.", &["RULE"]).is_none());
+		assert!(cache.get("en-US", "The text.", &["RULE"]).is_none());
+
+		// an entry of another chunk with the same key is not returned
+		let key = CheckCache::key("en-US", "The text.", &["RULE"]);
+		let entry = CacheEntry::new("en-US", "Teh text.", &["RULE"], Vec::new());
+		cache.entries.insert(key, entry);
+		assert!(cache.get("en-US", "The text.", &["RULE"]).is_none());
+	}
+
+	#[test]
+	fn test_disk_cache() {
+		let dir = std::env::temp_dir().join(format!(
+			"typst-languagetool-disk-cache-{}",
+			std::process::id()
+		));
+		let _ = std::fs::remove_dir_all(&dir);
+		let cache = DiskCache::new(&dir).unwrap();
+		let suggestion = Suggestion {
+			message: "Possible typo".into(),
+			replacements: vec!["The".into()],
+			rule_id: "RULE".into(),
+			category: "TYPOS".into(),
+			..suggestion(0, 3)
+		};
+		assert!(cache.get("en-US", "Teh text.", &()).is_none());
+		cache
+			.insert("en-US", "Teh text.", &(), &[suggestion])
+			.unwrap();
+		cache.insert("en-US", "Other text.", &(), &[]).unwrap();
+		let cached = cache.get("en-US", "Teh text.", &()).unwrap();
+		assert_eq!(cached[0].replacements, ["The"]);
+		assert!(cache.get("de-DE", "Teh text.", &()).is_none());
+
+		// an entry of another chunk with the same file name is not returned
+		std::fs::copy(
+			cache.path("en-US", "Teh text.", &()),
+			cache.path("en-US", "The text.", &()),
+		)
+		.unwrap();
+		assert!(cache.get("en-US", "The text.", &()).is_none());
+		std::fs::remove_file(cache.path("en-US", "The text.", &())).unwrap();
+
+		// the least recently used entry is evicted first
+		let size = std::fs::metadata(cache.path("en-US", "Teh text.", &()))
+			.unwrap()
+			.len();
+		let old = SystemTime::now() - Duration::from_secs(60);
+		std::fs::File::options()
+			.append(true)
+			.open(cache.path("en-US", "Other text.", &()))
+			.unwrap()
+			.set_modified(old)
+			.unwrap();
+		let cache = cache.with_max_size(size);
+		cache.evict().unwrap();
+		assert!(cache.get("en-US", "Teh text.", &()).is_some());
+		assert!(cache.get("en-US", "Other text.", &()).is_none());
+
+		let cache = cache.with_max_age(Duration::ZERO);
+		std::thread::sleep(Duration::from_millis(10));
+		cache.evict().unwrap();
+		assert!(cache.get("en-US", "Teh text.", &()).is_none());
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn test_filter_by_category() {
+		let suggestion = |rule_id: &str, category: &str| Suggestion {
+			rule_id: rule_id.into(),
+			category: category.into(),
+			..suggestion(0, 3)
+		};
+		let suggestions = vec![
+			suggestion("PASSIVE_VOICE", "STYLE"),
+			suggestion("HE_VERB_AGR", "GRAMMAR"),
+		];
+		let kept = filter_by_category(suggestions.clone(), &["GRAMMAR"]);
+		assert_eq!(kept.len(), 1);
+		assert_eq!(kept[0].rule_id, "HE_VERB_AGR");
+		assert!(filter_by_category(suggestions, &[] as &[&str]).is_empty());
+	}
+
+	#[test]
+	fn test_ignored_words() {
+		let world = world("A Kubernetes cluster with kubernetes nodes.");
+		let doc = world.compile().unwrap();
+		let source = world.main();
+		let (text, mapping) = convert::document(&doc, 1000, &source, &Default::default())
+			.unwrap()
+			.remove(0);
+		let suggestions = text
+			.match_indices(['K', 'k'])
+			.map(|(index, _)| {
+				let start = utf_16_index(&text, index);
+				suggestion(start, start + "kubernetes".len())
+			})
+			.collect::<Vec<_>>();
+		let diagnostics = |ignored| {
+			let mut collector = FileCollector::new(source.id(), &world)
+				.unwrap()
+				.with_ignored_words(ignored);
+			collector.add(&suggestions, mapping.clone());
+			collector.finish().1
+		};
+
+		assert_eq!(diagnostics(IgnoredWords::default()).len(), 2);
+		let remaining = diagnostics(IgnoredWords::new(["Kubernetes"], true));
+		assert_eq!(remaining.len(), 1);
+		assert_eq!(
+			&source.text()[remaining[0].locations[0].clone()],
+			"kubernetes"
+		);
+		assert!(diagnostics(IgnoredWords::new(["Kubernetes"], false)).is_empty());
+	}
+
+	#[test]
+	fn test_inserted_text() {
+		let world = world("First paragraph.\n\nSecond one.");
+		let doc = world.compile().unwrap();
+		let source = world.main();
+		let (text, mapping) = convert::document(&doc, 1000, &source, &Default::default())
+			.unwrap()
+			.remove(0);
+		let suggestion = |start, end| Suggestion {
+			replacements: vec![" ".into()],
+			rule_id: "WHITESPACE_RULE".into(),
+			category: "TYPOGRAPHY".into(),
+			..suggestion(utf_16_index(&text, start), utf_16_index(&text, end))
+		};
+		// the paragraph break between the paragraphs is inserted
+		let start = text.find(".\n\n").unwrap() + 1;
+		let inserted = suggestion(start, start + 2);
+		assert!(mapping.is_inserted(&inserted));
+		let start = text.find("Second").unwrap();
+		let spanning = suggestion(start - 2, start + 6);
+		assert!(mapping.is_inserted(&spanning).not());
+
+		let mut collector = FileCollector::new(source.id(), &world).unwrap();
+		collector.add(&[inserted, spanning], mapping);
+		let diagnostics = collector.finish().1;
+		assert_eq!(diagnostics.len(), 1);
+		assert_eq!(
+			&source.text()[diagnostics[0].locations[0].clone()],
+			"Second"
+		);
+	}
+
+	#[test]
+	fn test_max_length() {
+		let sentence = format!(
+			"A tpyo in a sentence {}that never ends.",
+			"and on ".repeat(40)
+		);
+		let world = world(&sentence);
+		let doc = world.compile().unwrap();
+		let source = world.main();
+		let (text, mapping) = convert::document(&doc, 1000, &source, &Default::default())
+			.unwrap()
+			.remove(0);
+		let suggestion =
+			|start, end| suggestion(utf_16_index(&text, start), utf_16_index(&text, end));
+		let typo = text.find("tpyo").unwrap();
+		let sentence = text.find('A').unwrap();
+		let suggestions = [suggestion(typo, typo + 4), suggestion(sentence, text.len())];
+		let mut collector = FileCollector::new(source.id(), &world)
+			.unwrap()
+			.with_max_length(200);
+		collector.add(&suggestions, mapping.clone());
+		let diagnostics = collector.finish().1;
+		assert_eq!(
+			diagnostics.iter().map(|d| d.long).collect::<Vec<_>>(),
+			[false, true]
+		);
+
+		let mut collector = FileCollector::new(source.id(), &world).unwrap();
+		collector.add(&suggestions, mapping);
+		assert!(collector.finish().1.iter().all(|d| d.long.not()));
+	}
+
 	#[test]
 	fn test_newline_stop() {
 		let text = "abc\ndef\nghi";
@@ -0,0 +1,149 @@
+use std::ops::{AddAssign, Not};
+
+use crate::convert::{ConvertOptions, Mapping};
+
+/// Word, sentence and syllable counts of converted text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+	pub words: usize,
+	pub sentences: usize,
+	/// Estimated from the groups of vowels of the words.
+	pub syllables: usize,
+}
+
+impl Stats {
+	/// Counts the words and sentences of the text, leaving out the placeholder of equations.
+	///
+	/// A sentence ends with terminal punctuation or a paragraph break, like after a heading.
+	pub fn text(text: &str, placeholder: &str) -> Self {
+		let mut stats = Self::default();
+		for paragraph in text.split("\n\n") {
+			let mut open = false;
+			for token in paragraph.split_whitespace() {
+				let word = token.trim_matches(|c: char| c.is_alphanumeric().not());
+				if word.is_empty() || token == placeholder || word == placeholder {
+					// punctuation after an equation still ends the sentence
+					if open && token.ends_with(['.', '!', '?']) {
+						stats.sentences += 1;
+						open = false;
+					}
+					continue;
+				}
+				stats.words += 1;
+				stats.syllables += syllables(word);
+				open = true;
+				if token
+					.trim_end_matches(['"', '\'', ')', ']'])
+					.ends_with(['.', '!', '?'])
+				{
+					stats.sentences += 1;
+					open = false;
+				}
+			}
+			if open {
+				stats.sentences += 1;
+			}
+		}
+		stats
+	}
+
+	/// Flesch reading ease, higher scores are easier to read.
+	///
+	/// The formula is tuned for English text.
+	pub fn flesch_reading_ease(&self) -> Option<f64> {
+		if self.words == 0 || self.sentences == 0 {
+			return None;
+		}
+		let words = self.words as f64;
+		Some(
+			206.835
+				- 1.015 * (words / self.sentences as f64)
+				- 84.6 * (self.syllables as f64 / words),
+		)
+	}
+}
+
+impl AddAssign for Stats {
+	fn add_assign(&mut self, other: Self) {
+		self.words += other.words;
+		self.sentences += other.sentences;
+		self.syllables += other.syllables;
+	}
+}
+
+/// Statistics of every chunk and of the whole document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentStats {
+	pub chunks: Vec<Stats>,
+	pub total: Stats,
+}
+
+/// Statistics of the chunks returned by [`crate::convert::document`].
+///
+/// The text repeated from the previous chunk is not counted again, a sentence split between
+/// chunks counts once for the document.
+pub fn stats(chunks: &[(String, Mapping)], options: &ConvertOptions) -> DocumentStats {
+	let mut res = DocumentStats::default();
+	for (text, mapping) in chunks {
+		let stats = Stats::text(mapping.checked_text(text), &options.math_placeholder);
+		res.total += stats;
+		if mapping.info().ends_mid_sentence && stats.sentences > 0 {
+			res.total.sentences -= 1;
+		}
+		res.chunks.push(stats);
+	}
+	res
+}
+
+fn syllables(word: &str) -> usize {
+	let word = word.to_lowercase();
+	let is_vowel = |c: char| "aeiouyäöüáéíóúàèìòùâêîôûë".contains(c);
+	let mut count = 0;
+	let mut previous = false;
+	for c in word.chars() {
+		let vowel = is_vowel(c);
+		if vowel && previous.not() {
+			count += 1;
+		}
+		previous = vowel;
+	}
+	// a silent e at the end, like in "make"
+	if count > 1 && word.ends_with('e') && word.ends_with("le").not() {
+		count -= 1;
+	}
+	count.max(1)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::convert::{self, test::compile};
+
+	#[test]
+	fn test_stats() {
+		let stats = Stats::text(
+			"This is a short sentence. It has 0 math!\n\n= A heading\n\nDoes 0 count?",
+			"0",
+		);
+		assert_eq!(stats.words, 5 + 3 + 2 + 2);
+		assert_eq!(stats.sentences, 4);
+		assert_eq!(Stats::text("", "0").flesch_reading_ease(), None);
+	}
+
+	#[test]
+	fn test_document_stats() {
+		let (doc, source) = compile(
+			"= Title\n\nThe cat sat on the mat. Equations like $x^2$ are not words.\n\nThis \
+			 sentence continues #pagebreak() on the next page.",
+		);
+		let options = ConvertOptions::default();
+		let chunks = convert::document(&doc, 1000, &source, &options).unwrap();
+		let stats = stats(&chunks, &options);
+		assert_eq!(stats.chunks.len(), 2);
+		assert_eq!(stats.chunks[0].words, 1 + 6 + 5 + 3);
+		assert_eq!(stats.chunks[0].sentences, 4);
+		assert_eq!(stats.total.words, 1 + 6 + 5 + 3 + 4);
+		assert_eq!(stats.total.sentences, 4);
+		assert!(stats.total.flesch_reading_ease().unwrap() > 60.0);
+	}
+}